@@ -0,0 +1,388 @@
+//! Scrub macOS metadata entries from zip and tar archives
+//!
+//! An entry is dropped when any component of its path is a [`Target`]:
+//! `.DS_Store`, an AppleDouble `._name` file or anything under `__MACOSX/`.
+//! Every other entry is copied as-is. Zip entries keep their compressed
+//! data and attributes, and tar entries keep their original headers,
+//! including long-name and PAX extension records. The archive is replaced
+//! atomically.
+
+use crate::killer::Target;
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use tar::EntryType;
+
+/// Supported archive formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    /// Format from the file extension
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether an entry path is a target or lies inside one
+pub fn is_target_entry(name: &str) -> bool {
+    name.split('/').any(|c| Target::of(c).is_some())
+}
+
+/// Target entries in `path`, without changing it
+pub fn check(path: &Path) -> Result<Vec<String>, String> {
+    run(path, false)
+}
+
+/// Rewrite `path` without its target entries; returns the removed entries
+///
+/// An archive without targets is left untouched.
+pub fn clean(path: &Path) -> Result<Vec<String>, String> {
+    run(path, true)
+}
+
+fn run(path: &Path, write: bool) -> Result<Vec<String>, String> {
+    let format = Format::detect(path)
+        .ok_or_else(|| format!("Unsupported archive (use .zip, .tar, .tar.gz or .tgz): {}", path.display()))?;
+    let err = |e: io::Error| format!("{}: {}", path.display(), e);
+
+    // Listing first keeps archives without targets byte-for-byte identical
+    let input = BufReader::new(File::open(path).map_err(err)?);
+    let removed = match format {
+        Format::Zip => copy_zip(input, None::<io::Cursor<Vec<u8>>>),
+        Format::Tar => copy_tar(input, None::<io::Sink>).map(|(removed, _)| removed),
+        Format::TarGz => copy_tar(MultiGzDecoder::new(input), None::<io::Sink>).map(|(removed, _)| removed),
+    }
+    .map_err(err)?;
+    if !write || removed.is_empty() {
+        return Ok(removed);
+    }
+
+    let tmp = temp_path(path);
+    let result = (|| {
+        let input = BufReader::new(File::open(path)?);
+        let output = BufWriter::new(File::create(&tmp)?);
+        match format {
+            Format::Zip => {
+                copy_zip(input, Some(output))?;
+            }
+            Format::Tar => {
+                if let (_, Some(mut out)) = copy_tar(input, Some(output))? {
+                    out.flush()?;
+                }
+            }
+            Format::TarGz => {
+                let gz = GzEncoder::new(output, Compression::default());
+                if let (_, Some(gz)) = copy_tar(MultiGzDecoder::new(input), Some(gz))? {
+                    gz.finish()?.flush()?;
+                }
+            }
+        }
+        fs::set_permissions(&tmp, fs::metadata(path)?.permissions())?;
+        fs::rename(&tmp, path)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(err(e));
+    }
+    Ok(removed)
+}
+
+/// Sibling file the rewritten archive is built in
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.dsk-tmp", name))
+}
+
+fn zip_err(e: zip::result::ZipError) -> io::Error {
+    match e {
+        zip::result::ZipError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// List target entries, copying every other entry to `out` if given
+fn copy_zip<R, W>(input: R, out: Option<W>) -> io::Result<Vec<String>>
+where
+    R: Read + io::Seek,
+    W: Write + io::Seek,
+{
+    let mut archive = zip::ZipArchive::new(input).map_err(zip_err)?;
+    let mut writer = out.map(zip::ZipWriter::new);
+    if let Some(w) = &mut writer {
+        w.set_raw_comment(archive.comment().into());
+    }
+
+    let mut removed = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i).map_err(zip_err)?;
+        if is_target_entry(file.name()) {
+            removed.push(file.name().to_string());
+        } else if let Some(w) = &mut writer {
+            w.raw_copy_file(file).map_err(zip_err)?;
+        }
+    }
+
+    if let Some(w) = writer {
+        w.finish().map_err(zip_err)?.flush()?;
+    }
+    Ok(removed)
+}
+
+/// List target entries, copying every other entry to `out` if given; `out`
+/// is handed back for the caller to finish
+///
+/// Entries are read raw, so long-name and PAX records arrive as entries of
+/// their own; they are held back and dropped or written together with the
+/// entry they describe. Only those small records are buffered, entry data
+/// is streamed.
+fn copy_tar<R: Read, W: Write>(input: R, out: Option<W>) -> io::Result<(Vec<String>, Option<W>)> {
+    let mut archive = tar::Archive::new(input);
+    let mut builder = out.map(tar::Builder::new);
+    let mut removed = Vec::new();
+    let mut pending: Vec<(tar::Header, Vec<u8>)> = Vec::new();
+    let mut long_name: Option<String> = None;
+
+    for entry in archive.entries()?.raw(true) {
+        let mut entry = entry?;
+        let header = entry.header().clone();
+
+        let kind = header.entry_type();
+        if matches!(kind, EntryType::GNULongName | EntryType::XHeader | EntryType::GNULongLink) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            match kind {
+                EntryType::GNULongName => long_name = Some(c_string(&data)),
+                EntryType::XHeader => {
+                    if let Some(p) = pax_path(&data) {
+                        long_name = Some(p);
+                    }
+                }
+                _ => {}
+            }
+            pending.push((header, data));
+            continue;
+        }
+
+        let name = long_name
+            .take()
+            .unwrap_or_else(|| String::from_utf8_lossy(&header.path_bytes()).into_owned());
+        let records = std::mem::take(&mut pending);
+        // Global PAX headers apply to the whole archive, not one entry
+        if header.entry_type() != EntryType::XGlobalHeader && is_target_entry(&name) {
+            removed.push(name);
+            continue;
+        }
+        if let Some(b) = &mut builder {
+            for (h, d) in records {
+                b.append(&h, d.as_slice())?;
+            }
+            b.append(&header, &mut entry)?;
+        }
+    }
+
+    let out = builder.map(tar::Builder::into_inner).transpose()?;
+    Ok((removed, out))
+}
+
+/// Text up to the first NUL
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// The `path` record of a PAX extended header (`<len> path=<value>\n`)
+fn pax_path(mut data: &[u8]) -> Option<String> {
+    let mut path = None;
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&data[..space]).ok()?.parse().ok()?;
+        let record = data.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        data = &data[len..];
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+    use zip::{DateTime, write::SimpleFileOptions};
+
+    const NAMES: [&str; 5] = [
+        "docs/readme.txt",
+        "docs/.DS_Store",
+        "__MACOSX/docs/._readme.txt",
+        "docs/._readme.txt",
+        "bin/tool",
+    ];
+
+    #[test]
+    fn test_is_target_entry() {
+        assert!(is_target_entry(".DS_Store"));
+        assert!(is_target_entry("a/b/.DS_Store"));
+        assert!(is_target_entry("__MACOSX/"));
+        assert!(is_target_entry("__MACOSX/a/x.txt"));
+        assert!(is_target_entry("a/._x.txt"));
+        assert!(!is_target_entry("a/b/x.txt"));
+        assert!(!is_target_entry("a/_MACOSX/x"));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect(Path::new("a.ZIP")), Some(Format::Zip));
+        assert_eq!(Format::detect(Path::new("a.tar")), Some(Format::Tar));
+        assert_eq!(Format::detect(Path::new("a.tar.gz")), Some(Format::TarGz));
+        assert_eq!(Format::detect(Path::new("a.tgz")), Some(Format::TarGz));
+        assert_eq!(Format::detect(Path::new("a.rar")), None);
+    }
+
+    #[test]
+    fn test_clean_zip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.zip");
+        let time = DateTime::from_date_and_time(2020, 5, 17, 10, 30, 0).unwrap();
+
+        let mut w = zip::ZipWriter::new(File::create(&path).unwrap());
+        for name in NAMES {
+            let opts = SimpleFileOptions::default().last_modified_time(time).unix_permissions(0o751);
+            w.start_file(name, opts).unwrap();
+            w.write_all(name.as_bytes()).unwrap();
+        }
+        w.set_comment("release");
+        w.finish().unwrap();
+
+        let before = fs::read(&path).unwrap();
+        assert_eq!(check(&path).unwrap().len(), 3);
+        assert_eq!(fs::read(&path).unwrap(), before, "check must not modify");
+
+        let removed = clean(&path).unwrap();
+        assert_eq!(removed, vec![NAMES[1], NAMES[2], NAMES[3]]);
+        assert!(check(&path).unwrap().is_empty());
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.comment(), b"release");
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(names.len(), 2);
+        let mut f = archive.by_name("bin/tool").unwrap();
+        assert_eq!(f.last_modified(), Some(time));
+        assert_eq!(f.unix_mode().map(|m| m & 0o777), Some(0o751));
+        let mut content = String::new();
+        f.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "bin/tool");
+
+        // Nothing left to remove: the file is not rewritten
+        let cleaned = fs::read(&path).unwrap();
+        assert!(clean(&path).unwrap().is_empty());
+        assert_eq!(fs::read(&path).unwrap(), cleaned);
+    }
+
+    fn build_tar(names: &[&str]) -> Vec<u8> {
+        let mut b = tar::Builder::new(Vec::new());
+        for name in names {
+            let mut h = tar::Header::new_gnu();
+            h.set_size(name.len() as u64);
+            h.set_mode(0o640);
+            h.set_mtime(1_600_000_000);
+            b.append_data(&mut h, name, name.as_bytes()).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    fn tar_entries<R: Read>(r: R) -> Vec<(String, u32, u64)> {
+        tar::Archive::new(r)
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                let h = e.header();
+                (e.path().unwrap().display().to_string(), h.mode().unwrap(), h.mtime().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_tar_with_long_names() {
+        let long = format!("{}/keep.txt", "d".repeat(120));
+        let long_target = format!("{}/.DS_Store", "d".repeat(120));
+        let mut names: Vec<&str> = NAMES.to_vec();
+        names.push(&long);
+        names.push(&long_target);
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.tar");
+        fs::write(&path, build_tar(&names)).unwrap();
+
+        let removed = clean(&path).unwrap();
+        assert_eq!(removed, vec![NAMES[1], NAMES[2], NAMES[3], long_target.as_str()]);
+
+        let entries = tar_entries(File::open(&path).unwrap());
+        let kept: Vec<&str> = entries.iter().map(|(n, _, _)| n.as_str()).collect();
+        assert_eq!(kept, vec![NAMES[0], NAMES[4], long.as_str()]);
+        assert!(entries.iter().all(|&(_, mode, mtime)| mode == 0o640 && mtime == 1_600_000_000));
+    }
+
+    #[test]
+    fn test_clean_tar_gz() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.tgz");
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&build_tar(&NAMES)).unwrap();
+        fs::write(&path, gz.finish().unwrap()).unwrap();
+
+        assert_eq!(check(&path).unwrap().len(), 3);
+        assert_eq!(clean(&path).unwrap().len(), 3);
+        let entries = tar_entries(MultiGzDecoder::new(Cursor::new(fs::read(&path).unwrap())));
+        assert_eq!(entries.len(), 2);
+        assert!(!dir.path().join(".a.tgz.dsk-tmp").exists());
+    }
+
+    #[test]
+    fn test_clean_multi_member_tar_gz() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a.tar.gz");
+        // Two gzip members, split between the second and third entry
+        let tar = build_tar(&NAMES);
+        let mut data = Vec::new();
+        for part in [&tar[..2048], &tar[2048..]] {
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            gz.write_all(part).unwrap();
+            data.extend(gz.finish().unwrap());
+        }
+        fs::write(&path, data).unwrap();
+
+        assert_eq!(check(&path).unwrap().len(), 3);
+        assert_eq!(clean(&path).unwrap().len(), 3);
+        let entries = tar_entries(MultiGzDecoder::new(File::open(&path).unwrap()));
+        let kept: Vec<&str> = entries.iter().map(|(n, _, _)| n.as_str()).collect();
+        assert_eq!(kept, vec![NAMES[0], NAMES[4]]);
+    }
+
+    #[test]
+    fn test_pax_path() {
+        let data = b"30 mtime=1600000000.123456789\n21 path=__MACOSX/x.t\n";
+        assert_eq!(pax_path(data).as_deref(), Some("__MACOSX/x.t"));
+        assert_eq!(pax_path(b"bogus"), None);
+    }
+}
@@ -77,6 +77,7 @@ pub fn save(dir: &Path, recursive: bool, files: &[PathBuf]) {
 /// - Cache TTL expired
 /// - Directory mtime changed (new files may exist)
 /// - Recursive mode (can't reliably detect subdirectory changes)
+///
 /// Returns verified files only (files that still exist)
 pub fn load_verified(dir: &Path, recursive: bool) -> Option<Vec<PathBuf>> {
     // IMPORTANT: Don't cache recursive scans - subdirectory changes are undetectable
@@ -111,7 +112,7 @@ pub fn load_verified(dir: &Path, recursive: bool) -> Option<Vec<PathBuf>> {
 
     // Load file paths
     let files: Vec<PathBuf> = lines
-        .filter_map(|l| l.ok())
+        .map_while(Result::ok)
         .map(PathBuf::from)
         .collect();
 
@@ -161,7 +162,7 @@ mod tests {
         assert!(found.contains(&f2));
 
         // Recursive should not load
-        save(path, true, &[f1.clone()]);
+        save(path, true, std::slice::from_ref(&f1));
         assert!(load_verified(path, true).is_none(), "Recursive cache should return None");
     }
 
@@ -173,7 +174,7 @@ mod tests {
         // Initial setup
         let f1 = path.join("f1");
         File::create(&f1).unwrap();
-        save(path, false, &[f1.clone()]);
+        save(path, false, std::slice::from_ref(&f1));
 
         // Verify loaded
         assert!(load_verified(path, false).is_some());
@@ -0,0 +1,119 @@
+//! Non-destructive CI check: report targets without deleting them
+
+use crate::{cli::CheckFormat, consts::TARGET_FILE, xml};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Path as shown in reports: relative to `base` when possible
+fn display_path(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Escape a GitHub workflow command property value
+fn gh_escape(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// Render check results in the requested format
+///
+/// `base` is the directory paths are reported relative to (usually the
+/// current working directory, so CI annotations resolve to repo files).
+pub fn render(format: CheckFormat, files: &[PathBuf], base: &Path) -> String {
+    let mut out = String::new();
+
+    match format {
+        CheckFormat::Plain => {
+            for f in files {
+                let _ = writeln!(out, "{}", display_path(f, base));
+            }
+            if files.is_empty() {
+                let _ = writeln!(out, "No {} files found", TARGET_FILE);
+            } else {
+                let _ = writeln!(out, "{} {} file(s) found", files.len(), TARGET_FILE);
+            }
+        }
+        CheckFormat::Github => {
+            for f in files {
+                let _ = writeln!(
+                    out,
+                    "::error file={},title=dsk::Unexpected {} file",
+                    gh_escape(&display_path(f, base)),
+                    TARGET_FILE
+                );
+            }
+        }
+        CheckFormat::Junit => {
+            let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            let _ = writeln!(
+                out,
+                r#"<testsuite name="dsk" tests="{}" failures="{}">"#,
+                files.len().max(1),
+                files.len()
+            );
+            if files.is_empty() {
+                let _ = writeln!(out, r#"  <testcase classname="dsk" name="no {}"/>"#, TARGET_FILE);
+            }
+            for f in files {
+                let name = xml::escape(&display_path(f, base));
+                let _ = writeln!(out, r#"  <testcase classname="dsk" name="{}">"#, name);
+                let _ = writeln!(
+                    out,
+                    r#"    <failure message="Unexpected {} file">{}</failure>"#,
+                    TARGET_FILE, name
+                );
+                let _ = writeln!(out, "  </testcase>");
+            }
+            let _ = writeln!(out, "</testsuite>");
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<PathBuf> {
+        vec![PathBuf::from("/repo/a/.DS_Store"), PathBuf::from("/repo/b,c/.DS_Store")]
+    }
+
+    #[test]
+    fn test_render_plain() {
+        let out = render(CheckFormat::Plain, &files(), Path::new("/repo"));
+        assert_eq!(out, "a/.DS_Store\nb,c/.DS_Store\n2 .DS_Store file(s) found\n");
+
+        let out = render(CheckFormat::Plain, &[], Path::new("/repo"));
+        assert_eq!(out, "No .DS_Store files found\n");
+    }
+
+    #[test]
+    fn test_render_github() {
+        let out = render(CheckFormat::Github, &files(), Path::new("/repo"));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "::error file=a/.DS_Store,title=dsk::Unexpected .DS_Store file");
+        assert_eq!(lines[1], "::error file=b%2Cc/.DS_Store,title=dsk::Unexpected .DS_Store file");
+    }
+
+    #[test]
+    fn test_render_junit() {
+        let out = render(CheckFormat::Junit, &files(), Path::new("/other"));
+        assert!(out.contains(r#"<testsuite name="dsk" tests="2" failures="2">"#));
+        assert!(out.contains(r#"<testcase classname="dsk" name="/repo/a/.DS_Store">"#));
+
+        let out = render(CheckFormat::Junit, &[PathBuf::from("/x/<&>/.DS_Store")], Path::new("/"));
+        assert!(out.contains("x/&lt;&amp;&gt;/.DS_Store"));
+
+        let out = render(CheckFormat::Junit, &[], Path::new("/"));
+        assert!(out.contains(r#"tests="1" failures="0""#));
+    }
+}
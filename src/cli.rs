@@ -1,10 +1,15 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use ds_store_killer::{config::Layer, killer, pause};
+use std::{path::PathBuf, time::Duration};
 
 /// Kill .DS_Store files on macOS
 #[derive(Parser)]
 #[command(name = "dsk", version, about)]
 pub struct Cli {
+    /// User config file (default: $DSK_CONFIG or ~/.config/dsk/config.toml)
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -13,13 +18,29 @@ pub struct Cli {
 #[derive(clap::Args, Clone)]
 pub struct KillArgs {
     /// Target directory
-    #[arg(default_value = ".")]
+    #[arg(default_value = ".", conflicts_with = "list")]
     pub path: PathBuf,
 
+    /// Take targets and directories to scan from this file, one per line
+    #[arg(long, value_name = "FILE", group = "list")]
+    pub from_file: Option<PathBuf>,
+
+    /// Take targets and directories to scan from standard input
+    #[arg(long, group = "list")]
+    pub stdin: bool,
+
+    /// The list is NUL-separated (find -print0, fd -0)
+    #[arg(short = '0', long, requires = "list")]
+    pub null: bool,
+
     /// Recursive deletion
-    #[arg(short, long)]
+    #[arg(short, long, overrides_with = "no_recursive")]
     pub recursive: bool,
 
+    /// Turn off `recursive` set in a config file
+    #[arg(long, overrides_with = "recursive")]
+    pub no_recursive: bool,
+
     /// Exclude patterns
     #[arg(short, long)]
     pub exclude: Vec<String>,
@@ -40,21 +61,235 @@ pub struct KillArgs {
     #[arg(long)]
     pub stats: bool,
 
-    /// Force delete git-tracked .DS_Store files (default: skip them)
+    /// Parallel workers for git checks and deletion (default: CPU count,
+    /// at most 4 per CPU)
+    #[arg(
+        short,
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=4096)
+    )]
+    pub jobs: Option<usize>,
+
+    /// Print results in the order files were found, even with several jobs
     #[arg(long)]
+    pub ordered: bool,
+
+    /// Force delete git-tracked .DS_Store files (default: skip them)
+    #[arg(long, overrides_with = "no_force")]
     pub force: bool,
+
+    /// Turn off `force` set in a config file
+    #[arg(long, overrides_with = "force")]
+    pub no_force: bool,
+
+    /// Send a macOS notification when files were deleted
+    #[arg(long, overrides_with = "no_notify")]
+    pub notify: bool,
+
+    /// Turn off `notify` set in a config file
+    #[arg(long, overrides_with = "notify")]
+    pub no_notify: bool,
+
+    /// Also remove __MACOSX folders that hold only ._* files
+    #[arg(long, overrides_with = "no_macosx")]
+    pub macosx: bool,
+
+    /// Turn off `macosx` set in a config file
+    #[arg(long, overrides_with = "macosx")]
+    pub no_macosx: bool,
+
+    /// Never delete under paths/directories matching this pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub keep: Vec<String>,
+
+    /// Age and size filters
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// File system boundaries
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+
+    /// Output format (json requires --yes or --dry-run)
+    #[arg(long, value_enum, default_value_t = KillFormat::Human)]
+    pub format: KillFormat,
 }
 
-/// Arguments for watch command
+/// Metadata filters shared by kill and watch
+#[derive(clap::Args, Clone, Default)]
+pub struct FilterArgs {
+    /// Only delete files last modified at least this long ago (e.g. 7d)
+    #[arg(long, value_name = "DURATION", value_parser = pause::parse_duration)]
+    pub older_than: Option<Duration>,
+
+    /// Only delete files last modified within this duration
+    #[arg(long, value_name = "DURATION", value_parser = pause::parse_duration)]
+    pub newer_than: Option<Duration>,
+
+    /// Only delete files at least this large (e.g. 1k)
+    #[arg(long, value_name = "SIZE", value_parser = killer::parse_size)]
+    pub min_size: Option<u64>,
+
+    /// Leave larger files in place and flag them (e.g. 1M)
+    #[arg(long, value_name = "SIZE", value_parser = killer::parse_size)]
+    pub max_size: Option<u64>,
+}
+
+impl FilterArgs {
+    /// Replace every filter with the merged configuration
+    fn apply(&mut self, cfg: &Layer) {
+        self.older_than = cfg.older_than;
+        self.newer_than = cfg.newer_than;
+        self.min_size = cfg.min_size;
+        self.max_size = cfg.max_size;
+    }
+
+    pub fn to_filter(&self) -> killer::Filter {
+        let mut filter = killer::Filter::default();
+        filter.older_than = self.older_than;
+        filter.newer_than = self.newer_than;
+        filter.min_size = self.min_size;
+        filter.max_size = self.max_size;
+        filter
+    }
+}
+
+/// File system boundaries for recursive walks
+#[derive(clap::Args, Clone, Default)]
+pub struct TraversalArgs {
+    /// Stay on the file system of the target directory
+    #[arg(short = 'x', long, overrides_with = "no_one_file_system")]
+    pub one_file_system: bool,
+
+    /// Turn off `one_file_system` set in a config file
+    #[arg(long, overrides_with = "one_file_system")]
+    pub no_one_file_system: bool,
+
+    /// Descend into symlinked directories (each directory is read once)
+    #[arg(long, overrides_with = "no_follow_symlinks")]
+    pub follow_symlinks: bool,
+
+    /// Turn off `follow_symlinks` set in a config file
+    #[arg(long, overrides_with = "follow_symlinks")]
+    pub no_follow_symlinks: bool,
+
+    /// Don't look deeper than N levels below the target directory
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Only delete targets at least N levels below the target directory
+    #[arg(long, value_name = "N")]
+    pub min_depth: Option<usize>,
+}
+
+impl TraversalArgs {
+    /// The traversal options as a config layer
+    fn flags(&self) -> Layer {
+        Layer {
+            one_file_system: flag(self.one_file_system, self.no_one_file_system),
+            follow_symlinks: flag(self.follow_symlinks, self.no_follow_symlinks),
+            max_depth: self.max_depth,
+            min_depth: self.min_depth,
+            ..Layer::default()
+        }
+    }
+
+    fn apply(&mut self, cfg: &Layer) {
+        self.one_file_system = cfg.one_file_system.unwrap_or(false);
+        self.follow_symlinks = cfg.follow_symlinks.unwrap_or(false);
+        self.max_depth = cfg.max_depth;
+        self.min_depth = cfg.min_depth;
+    }
+
+    pub fn to_traversal(&self) -> killer::Traversal {
+        let mut traversal = killer::Traversal::default();
+        traversal.one_file_system = self.one_file_system;
+        traversal.follow_symlinks = self.follow_symlinks;
+        traversal.max_depth = self.max_depth;
+        traversal.min_depth = self.min_depth;
+        traversal
+    }
+}
+
+/// Arguments for check command
 #[derive(clap::Args, Clone)]
-pub struct WatchArgs {
+pub struct CheckArgs {
     /// Target directory
     #[arg(default_value = ".")]
     pub path: PathBuf,
 
+    /// Recursive scan
+    #[arg(short, long, overrides_with = "no_recursive")]
+    pub recursive: bool,
+
+    /// Turn off `recursive` set in a config file
+    #[arg(long, overrides_with = "recursive")]
+    pub no_recursive: bool,
+
+    /// Exclude patterns
+    #[arg(short, long)]
+    pub exclude: Vec<String>,
+
+    /// Only fail on git-tracked .DS_Store files
+    #[arg(long)]
+    pub tracked_only: bool,
+
+    /// File system boundaries
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = CheckFormat::Plain)]
+    pub format: CheckFormat,
+}
+
+/// Report format for check command
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum CheckFormat {
+    /// One path per line plus a summary
+    #[default]
+    Plain,
+    /// GitHub Actions error annotations
+    Github,
+    /// JUnit XML report
+    Junit,
+}
+
+/// Output format for kill command
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KillFormat {
+    /// Colored human-readable text
+    #[default]
+    Human,
+    /// A single JSON summary object
+    Json,
+}
+
+/// Output format for watch command
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WatchFormat {
+    /// Colored human-readable text
+    #[default]
+    Human,
+    /// One JSON event per line
+    Ndjson,
+}
+
+/// Arguments for watch command
+#[derive(clap::Args, Clone)]
+pub struct WatchArgs {
+    /// Directories to watch (nested ones are covered by their parent)
+    #[arg(default_value = ".")]
+    pub paths: Vec<PathBuf>,
+
     /// Shared watch options
     #[command(flatten)]
     pub options: WatchSharedArgs,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = WatchFormat::Human)]
+    pub format: WatchFormat,
 }
 
 #[derive(Subcommand)]
@@ -72,6 +307,60 @@ pub enum Commands {
         args: WatchArgs,
     },
 
+    /// Check for .DS_Store files without deleting (exits 2 if any found)
+    Check {
+        #[command(flatten)]
+        args: CheckArgs,
+    },
+
+    /// Control a running watcher through its socket
+    Ctl {
+        /// Control socket of the watcher
+        #[arg(long, value_name = "PATH", global = true)]
+        socket: Option<PathBuf>,
+
+        #[command(subcommand)]
+        action: CtlAction,
+    },
+
+    /// Stop the watcher from deleting under PATH for a while
+    Pause {
+        /// Directory to leave alone
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// How long, e.g. 90s, 30m, 2h, 1d
+        #[arg(long = "for", value_name = "DURATION", value_parser = pause::parse_duration)]
+        duration: Duration,
+
+        /// Control socket of the watcher
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+
+    /// Lift a pause before it expires
+    Resume {
+        /// Paused directory
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Control socket of the watcher
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+
+    /// Remove metadata entries from zip and tar archives
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
+
+    /// Inspect configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
     /// Manage launchd service
     Service {
         #[command(subcommand)]
@@ -79,6 +368,54 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum CtlAction {
+    /// Print live statistics as JSON
+    Stats,
+    /// Stop deleting until resumed
+    Pause,
+    /// Resume and rescan every root
+    Resume,
+    /// Scan a directory inside a watched root now
+    Rescan { path: PathBuf },
+    /// Start watching another root
+    AddRoot { path: PathBuf },
+    /// Stop watching a root
+    RemoveRoot { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum ArchiveAction {
+    /// Rewrite archives without .DS_Store, ._* and __MACOSX/ entries
+    Clean {
+        /// Archives (.zip, .tar, .tar.gz, .tgz)
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Only report entries; exit 2 if any are found
+        #[arg(long)]
+        check: bool,
+
+        /// Quiet mode: suppress entry listing
+        #[arg(short, long)]
+        quiet: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Show the config layers that apply to a directory
+    Show {
+        /// Directory whose `.dsk.toml` applies
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Show merged values and where each one comes from
+        #[arg(long)]
+        effective: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum ServiceAction {
     /// Install launchd plist
@@ -88,7 +425,7 @@ pub enum ServiceAction {
         paths: Vec<String>,
 
         #[command(flatten)]
-        watch_args: WatchSharedArgs,
+        watch_args: Box<WatchSharedArgs>,
     },
     /// Uninstall launchd plist
     Uninstall,
@@ -108,10 +445,138 @@ pub struct WatchSharedArgs {
     pub exclude: Vec<String>,
 
     /// Send macOS notification on delete
-    #[arg(long)]
+    #[arg(long, overrides_with = "no_notify")]
     pub notify: bool,
 
+    /// Turn off `notify` set in a config file
+    #[arg(long, overrides_with = "notify")]
+    pub no_notify: bool,
+
     /// Force delete git-tracked .DS_Store files
-    #[arg(long)]
+    #[arg(long, overrides_with = "no_force")]
     pub force: bool,
+
+    /// Turn off `force` set in a config file
+    #[arg(long, overrides_with = "force")]
+    pub no_force: bool,
+
+    /// Never delete under paths/directories matching this pattern (repeatable)
+    #[arg(long, value_name = "PATTERN")]
+    pub keep: Vec<String>,
+
+    /// Age and size filters
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// File system boundaries for scans
+    #[command(flatten)]
+    pub traversal: TraversalArgs,
+
+    /// Wait until a file has been quiet this long before acting [default: 300]
+    #[arg(long, value_name = "MS")]
+    pub debounce: Option<u64>,
+
+    /// Rescan every watched root at this interval (0 = off) [default: 0]
+    #[arg(long, value_name = "SECS")]
+    pub sweep: Option<u64>,
+
+    /// Poll instead of using file system events (all roots unless --poll-path)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub poll: Option<u64>,
+
+    /// Poll only this root (repeatable)
+    #[arg(long, value_name = "PATH")]
+    pub poll_path: Vec<PathBuf>,
+
+    /// Don't auto-select polling for network/FUSE mounts
+    #[arg(long)]
+    pub no_auto_poll: bool,
+
+    /// Ignore the saved checkpoint and scan every root on start
+    #[arg(long)]
+    pub full_scan: bool,
+
+    /// Serve the control socket here (default ~/Library/Caches/dsk/ctl.sock)
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+}
+
+/// Set flags as a config layer: `--x` turns an option on, `--no-x` off,
+/// and absent flags leave lower layers in effect
+fn flag(set: bool, unset: bool) -> Option<bool> {
+    if set { Some(true) } else if unset { Some(false) } else { None }
+}
+
+impl KillArgs {
+    pub fn flags(&self) -> Layer {
+        Layer {
+            recursive: flag(self.recursive, self.no_recursive),
+            exclude: self.exclude.clone(),
+            force: flag(self.force, self.no_force),
+            notify: flag(self.notify, self.no_notify),
+            macosx: flag(self.macosx, self.no_macosx),
+            keep: self.keep.clone(),
+            older_than: self.filter.older_than,
+            newer_than: self.filter.newer_than,
+            min_size: self.filter.min_size,
+            max_size: self.filter.max_size,
+            ..self.traversal.flags()
+        }
+    }
+
+    pub fn apply(&mut self, cfg: &Layer) {
+        self.recursive = cfg.recursive.unwrap_or(false);
+        self.exclude = cfg.exclude.clone();
+        self.force = cfg.force.unwrap_or(false);
+        self.notify = cfg.notify.unwrap_or(false);
+        self.macosx = cfg.macosx.unwrap_or(false);
+        self.keep = cfg.keep.clone();
+        self.filter.apply(cfg);
+        self.traversal.apply(cfg);
+    }
+}
+
+impl CheckArgs {
+    pub fn flags(&self) -> Layer {
+        Layer {
+            recursive: flag(self.recursive, self.no_recursive),
+            exclude: self.exclude.clone(),
+            ..self.traversal.flags()
+        }
+    }
+
+    pub fn apply(&mut self, cfg: &Layer) {
+        self.recursive = cfg.recursive.unwrap_or(false);
+        self.exclude = cfg.exclude.clone();
+        self.traversal.apply(cfg);
+    }
+}
+
+impl WatchSharedArgs {
+    pub fn flags(&self) -> Layer {
+        Layer {
+            exclude: self.exclude.clone(),
+            force: flag(self.force, self.no_force),
+            notify: flag(self.notify, self.no_notify),
+            keep: self.keep.clone(),
+            debounce: self.debounce,
+            sweep: self.sweep,
+            older_than: self.filter.older_than,
+            newer_than: self.filter.newer_than,
+            min_size: self.filter.min_size,
+            max_size: self.filter.max_size,
+            ..self.traversal.flags()
+        }
+    }
+
+    pub fn apply(&mut self, cfg: &Layer) {
+        self.exclude = cfg.exclude.clone();
+        self.force = cfg.force.unwrap_or(false);
+        self.notify = cfg.notify.unwrap_or(false);
+        self.keep = cfg.keep.clone();
+        self.debounce = cfg.debounce;
+        self.sweep = cfg.sweep;
+        self.filter.apply(cfg);
+        self.traversal.apply(cfg);
+    }
 }
@@ -0,0 +1,628 @@
+//! Layered configuration shared by kill, check, watch and service install
+//!
+//! Options are merged from, lowest to highest precedence: built-in defaults,
+//! the user config file, the nearest `.dsk.toml`, `DSK_*` environment
+//! variables and command-line flags. A scalar comes from the highest layer
+//! that sets it; the `exclude` and `keep` lists are combined across layers.
+//!
+//! Config files use a flat subset of TOML: `key = value` lines holding
+//! booleans, integers, strings or arrays of strings, with `#` comments.
+
+use crate::{killer, log, pause};
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+    time::Duration,
+};
+
+/// Per-directory config file name
+pub const LOCAL_FILE: &str = ".dsk.toml";
+
+/// Every option, in display order
+const KEYS: [&str; 16] = [
+    "recursive",
+    "exclude",
+    "force",
+    "notify",
+    "macosx",
+    "keep",
+    "debounce",
+    "sweep",
+    "older_than",
+    "newer_than",
+    "min_size",
+    "max_size",
+    "one_file_system",
+    "follow_symlinks",
+    "max_depth",
+    "min_depth",
+];
+
+/// Options set by one layer; `None` or empty means "not set here"
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layer {
+    pub recursive: Option<bool>,
+    pub exclude: Vec<String>,
+    pub force: Option<bool>,
+    pub notify: Option<bool>,
+    /// Also remove `__MACOSX` folders
+    pub macosx: Option<bool>,
+    pub keep: Vec<String>,
+    /// Watch debounce window in milliseconds
+    pub debounce: Option<u64>,
+    /// Watch sweep interval in seconds
+    pub sweep: Option<u64>,
+    pub older_than: Option<Duration>,
+    pub newer_than: Option<Duration>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub one_file_system: Option<bool>,
+    pub follow_symlinks: Option<bool>,
+    /// Deepest level (relative to the target directory) to look at
+    pub max_depth: Option<usize>,
+    /// Shallowest level to delete targets at
+    pub min_depth: Option<usize>,
+}
+
+/// A parsed config value
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Bool(bool),
+    Int(u64),
+    Str(String),
+    List(Vec<String>),
+}
+
+impl Value {
+    fn bool(self) -> Result<bool, String> {
+        match self {
+            Self::Bool(b) => Ok(b),
+            Self::Str(s) => match s.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                _ => Err(format!("expected a boolean, got {}", s)),
+            },
+            v => Err(format!("expected a boolean, got {:?}", v)),
+        }
+    }
+
+    fn int(self) -> Result<u64, String> {
+        match self {
+            Self::Int(n) => Ok(n),
+            Self::Str(s) => s.parse().map_err(|_| format!("expected a number, got {}", s)),
+            v => Err(format!("expected a number, got {:?}", v)),
+        }
+    }
+
+    /// Plain seconds or a duration string such as `7d`
+    fn duration(self) -> Result<Duration, String> {
+        match self {
+            // Same bounds as a duration string
+            Self::Int(n) => pause::parse_duration(&n.to_string()),
+            Self::Str(s) => pause::parse_duration(&s),
+            v => Err(format!("expected a duration, got {:?}", v)),
+        }
+    }
+
+    /// Plain bytes or a size string such as `1M`
+    fn size(self) -> Result<u64, String> {
+        match self {
+            Self::Int(n) => Ok(n),
+            Self::Str(s) => killer::parse_size(&s),
+            v => Err(format!("expected a size, got {:?}", v)),
+        }
+    }
+
+    fn list(self) -> Result<Vec<String>, String> {
+        match self {
+            Self::List(v) => Ok(v),
+            Self::Str(s) => Ok(vec![s]),
+            v => Err(format!("expected a list of strings, got {:?}", v)),
+        }
+    }
+}
+
+impl Layer {
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "recursive" => self.recursive = Some(value.bool()?),
+            "exclude" => self.exclude = value.list()?,
+            "force" => self.force = Some(value.bool()?),
+            "notify" => self.notify = Some(value.bool()?),
+            "macosx" => self.macosx = Some(value.bool()?),
+            "keep" => self.keep = value.list()?,
+            "debounce" => self.debounce = Some(value.int()?),
+            "sweep" => self.sweep = Some(value.int()?),
+            "older_than" => self.older_than = Some(value.duration()?),
+            "newer_than" => self.newer_than = Some(value.duration()?),
+            "min_size" => self.min_size = Some(value.size()?),
+            "max_size" => self.max_size = Some(value.size()?),
+            "one_file_system" => self.one_file_system = Some(value.bool()?),
+            "follow_symlinks" => self.follow_symlinks = Some(value.bool()?),
+            "max_depth" => self.max_depth = Some(value.int()? as usize),
+            "min_depth" => self.min_depth = Some(value.int()? as usize),
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// Parse the content of a config file
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut layer = Self::default();
+        let mut seen = Vec::new();
+        for (line, key, value) in parse_toml(text)? {
+            if seen.contains(&key) {
+                return Err(format!("line {}: `{}` is set twice", line, key));
+            }
+            layer.set(&key, value).map_err(|e| format!("line {}: {}", line, e))?;
+            seen.push(key);
+        }
+        Ok(layer)
+    }
+
+    /// Options from `DSK_<KEY>` variables looked up with `get`
+    ///
+    /// Lists are comma-separated; booleans accept 1/0, true/false, yes/no
+    /// and on/off.
+    pub fn from_env(get: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let mut layer = Self::default();
+        for key in KEYS {
+            let var = env_var(key);
+            let Some(raw) = get(&var) else { continue };
+            let value = if matches!(key, "exclude" | "keep") {
+                Value::List(raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+            } else {
+                Value::Str(raw.trim().to_string())
+            };
+            layer.set(key, value).map_err(|e| format!("{}: {}", var, e))?;
+        }
+        Ok(layer)
+    }
+
+    /// Value of `key` in TOML form, if this layer sets it
+    fn value(&self, key: &str) -> Option<String> {
+        let list = |v: &[String]| {
+            (!v.is_empty()).then(|| {
+                let items: Vec<String> = v.iter().map(|s| quote(s)).collect();
+                format!("[{}]", items.join(", "))
+            })
+        };
+        match key {
+            "recursive" => self.recursive.map(|b| b.to_string()),
+            "exclude" => list(&self.exclude),
+            "force" => self.force.map(|b| b.to_string()),
+            "notify" => self.notify.map(|b| b.to_string()),
+            "macosx" => self.macosx.map(|b| b.to_string()),
+            "keep" => list(&self.keep),
+            "debounce" => self.debounce.map(|n| n.to_string()),
+            "sweep" => self.sweep.map(|n| n.to_string()),
+            "older_than" => self.older_than.map(|d| d.as_secs().to_string()),
+            "newer_than" => self.newer_than.map(|d| d.as_secs().to_string()),
+            "min_size" => self.min_size.map(|n| n.to_string()),
+            "max_size" => self.max_size.map(|n| n.to_string()),
+            "one_file_system" => self.one_file_system.map(|b| b.to_string()),
+            "follow_symlinks" => self.follow_symlinks.map(|b| b.to_string()),
+            "max_depth" => self.max_depth.map(|n| n.to_string()),
+            "min_depth" => self.min_depth.map(|n| n.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The options this layer sets, one `key = value` line each
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for key in KEYS {
+            if let Some(v) = self.value(key) {
+                let _ = writeln!(out, "{} = {}", key, v);
+            }
+        }
+        out
+    }
+}
+
+/// Built-in values of options that have one
+fn default_value(key: &str) -> Option<String> {
+    match key {
+        "recursive" | "force" | "notify" | "macosx" | "one_file_system" | "follow_symlinks" => Some("false".into()),
+        "exclude" | "keep" => Some("[]".into()),
+        "debounce" => Some(crate::consts::DEBOUNCE_MS.to_string()),
+        "sweep" => Some("0".into()),
+        _ => None,
+    }
+}
+
+fn env_var(key: &str) -> String {
+    format!("DSK_{}", key.to_ascii_uppercase())
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// One layer and where it came from
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// File path, `environment` or `flags`
+    pub name: String,
+    pub layer: Layer,
+}
+
+/// The user config file: `--config`, `$DSK_CONFIG`,
+/// `$XDG_CONFIG_HOME/dsk/config.toml` or `~/.config/dsk/config.toml`
+///
+/// The flag is true when the file was named explicitly and so must exist.
+pub fn user_file(config: Option<&Path>) -> (PathBuf, bool) {
+    if let Some(path) = config {
+        return (PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref()), true);
+    }
+    if let Some(path) = env::var_os("DSK_CONFIG") {
+        return (PathBuf::from(path), true);
+    }
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.config").as_ref()));
+    (base.join("dsk/config.toml"), false)
+}
+
+/// The nearest `.dsk.toml` in `dir` or a directory above it
+pub fn local_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|d| d.join(LOCAL_FILE)).find(|f| f.is_file())
+}
+
+/// `.dsk.toml` files of `roots` other than the first root's
+///
+/// Options are shared by every root of a watcher, so only the first root's
+/// file applies; these are the ones left out.
+pub fn ignored_local_files(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let Some((first, rest)) = roots.split_first() else { return Vec::new() };
+    let used = local_file(first);
+    let mut out: Vec<PathBuf> = Vec::new();
+    for file in rest.iter().filter_map(|r| local_file(r)) {
+        if Some(&file) != used.as_ref() && !out.contains(&file) {
+            out.push(file);
+        }
+    }
+    out
+}
+
+fn read_file(path: &Path) -> Result<Layer, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Layer::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Every layer that applies to `dir`, lowest precedence first
+pub fn sources(config: Option<&Path>, dir: &Path, flags: Layer) -> Result<Vec<Source>, String> {
+    let mut out = Vec::new();
+
+    let (user, explicit) = user_file(config);
+    if explicit || user.is_file() {
+        out.push(Source { name: log::shorten_path(&user), layer: read_file(&user)? });
+    }
+    if let Some(local) = local_file(dir) {
+        let name = log::shorten_path(&local);
+        let mut layer = read_file(&local)?;
+        // A cloned repository must not be able to switch off the git check
+        if layer.force == Some(true) {
+            log::warn(&format!(
+                "Ignoring `force = true` in {}; use the user config, DSK_FORCE or --force",
+                name
+            ));
+            layer.force = None;
+        }
+        out.push(Source { name, layer });
+    }
+    let env = Layer::from_env(|k| env::var(k).ok())?;
+    if env != Layer::default() {
+        out.push(Source { name: "environment".into(), layer: env });
+    }
+    if flags != Layer::default() {
+        out.push(Source { name: "flags".into(), layer: flags });
+    }
+    Ok(out)
+}
+
+/// Merge layers, later ones taking precedence
+pub fn merge(sources: &[Source]) -> Layer {
+    let mut out = Layer::default();
+    for Source { layer: l, .. } in sources {
+        out.recursive = l.recursive.or(out.recursive);
+        out.force = l.force.or(out.force);
+        out.notify = l.notify.or(out.notify);
+        out.macosx = l.macosx.or(out.macosx);
+        out.debounce = l.debounce.or(out.debounce);
+        out.sweep = l.sweep.or(out.sweep);
+        out.older_than = l.older_than.or(out.older_than);
+        out.newer_than = l.newer_than.or(out.newer_than);
+        out.min_size = l.min_size.or(out.min_size);
+        out.max_size = l.max_size.or(out.max_size);
+        out.one_file_system = l.one_file_system.or(out.one_file_system);
+        out.follow_symlinks = l.follow_symlinks.or(out.follow_symlinks);
+        out.max_depth = l.max_depth.or(out.max_depth);
+        out.min_depth = l.min_depth.or(out.min_depth);
+        for e in &l.exclude {
+            if !out.exclude.contains(e) {
+                out.exclude.push(e.clone());
+            }
+        }
+        for k in &l.keep {
+            if !out.keep.contains(k) {
+                out.keep.push(k.clone());
+            }
+        }
+    }
+    out
+}
+
+/// Effective settings for `dir`: the merged layers under `flags`
+pub fn resolve(config: Option<&Path>, dir: &Path, flags: Layer) -> Result<Layer, String> {
+    Ok(merge(&sources(config, dir, flags)?))
+}
+
+/// Every option with its effective value and the layer(s) it came from
+pub fn describe(sources: &[Source]) -> String {
+    let merged = merge(sources);
+    let mut out = String::new();
+    for key in KEYS {
+        let origins: Vec<&str> = if matches!(key, "exclude" | "keep") {
+            sources.iter().filter(|s| s.layer.value(key).is_some()).map(|s| s.name.as_str()).collect()
+        } else {
+            sources.iter().rev().find(|s| s.layer.value(key).is_some()).map(|s| s.name.as_str()).into_iter().collect()
+        };
+        let line = match (merged.value(key), default_value(key)) {
+            (Some(v), _) => format!("{} = {}", key, v),
+            (None, Some(v)) => format!("{} = {}", key, v),
+            (None, None) => format!("# {} is not set", key),
+        };
+        let origin = if origins.is_empty() { "default".to_string() } else { origins.join(", ") };
+        if line.starts_with('#') {
+            let _ = writeln!(out, "{}", line);
+        } else {
+            let _ = writeln!(out, "{:<32} # {}", line, origin);
+        }
+    }
+    out
+}
+
+/// Parse `key = value` lines into (line number, key, value)
+fn parse_toml(text: &str) -> Result<Vec<(usize, String, Value)>, String> {
+    let mut s = Lexer { chars: text.chars().peekable(), line: 1 };
+    let mut out = Vec::new();
+    loop {
+        s.skip_blank(true);
+        let Some(c) = s.chars.peek().copied() else { break };
+        let line = s.line;
+        if c == '[' {
+            return Err(s.error("tables are not supported; put options at the top level"));
+        }
+        let key = s.word();
+        if key.is_empty() {
+            return Err(s.error(&format!("unexpected `{}`", c)));
+        }
+        s.skip_blank(false);
+        if s.chars.next() != Some('=') {
+            return Err(s.error(&format!("expected `=` after `{}`", key)));
+        }
+        s.skip_blank(false);
+        let value = s.value()?;
+        s.skip_blank(false);
+        match s.chars.next() {
+            None => {}
+            Some('\n') => s.line += 1,
+            Some(c) => return Err(s.error(&format!("unexpected `{}` after value", c))),
+        }
+        out.push((line, key, value));
+    }
+    Ok(out)
+}
+
+/// Character cursor over a config file, tracking the line number
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Lexer<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {}", self.line, msg)
+    }
+
+    /// Skip spaces and comments, and newlines too if `newlines`
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' => {
+                    while self.chars.next_if(|&c| c != '\n').is_some() {}
+                    continue;
+                }
+                _ => break,
+            }
+            self.chars.next();
+        }
+    }
+
+    /// A bare key, boolean or integer
+    fn word(&mut self) -> String {
+        let mut w = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+')) {
+            w.push(c);
+        }
+        w
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::Str),
+            Some('[') => {
+                self.chars.next();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank(true);
+                    if self.chars.next_if_eq(&']').is_some() {
+                        break;
+                    }
+                    if !matches!(self.chars.peek(), Some('"') | Some('\'')) {
+                        return Err(self.error("arrays may only hold strings"));
+                    }
+                    items.push(self.string()?);
+                    self.skip_blank(true);
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => break,
+                        _ => return Err(self.error("expected `,` or `]` in array")),
+                    }
+                }
+                Ok(Value::List(items))
+            }
+            _ => {
+                let w = self.word();
+                match w.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "" => Err(self.error("expected a value")),
+                    _ => w
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Int)
+                        .map_err(|_| self.error(&format!("invalid value `{}`", w))),
+                }
+            }
+        }
+    }
+
+    /// A basic (`"..."`, with escapes) or literal (`'...'`) string
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.chars.next();
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) if Some(c) == quote => return Ok(out),
+                Some('\\') if quote == Some('"') => match self.chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c @ ('"' | '\\')) => out.push(c),
+                    _ => return Err(self.error("unsupported escape in string")),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+# Options for every run
+recursive = true
+exclude = [
+    "node_modules",   # big
+    'build\out',
+]
+keep = "*.dmgproj"
+debounce = 1_000
+older_than = "7d"
+max_size = 65536
+"#;
+        let layer = Layer::parse(text).unwrap();
+        assert_eq!(layer.recursive, Some(true));
+        assert_eq!(layer.exclude, vec!["node_modules", "build\\out"]);
+        assert_eq!(layer.keep, vec!["*.dmgproj"]);
+        assert_eq!(layer.debounce, Some(1000));
+        assert_eq!(layer.older_than, Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(layer.max_size, Some(65536));
+        assert_eq!(layer.force, None);
+
+        // Rendering parses back to the same layer
+        assert_eq!(Layer::parse(&layer.render()), Ok(layer));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |text: &str| Layer::parse(text).unwrap_err();
+        assert!(err("force = true\nfrce = true").starts_with("line 2: unknown option"));
+        assert!(err("force = 1.5").contains("line 1"));
+        assert!(err("[watch]\nnotify = true").contains("tables"));
+        assert!(err("exclude = [1, 2]").contains("strings"));
+        assert!(err("keep = \"open").contains("unterminated"));
+        assert!(err("force = true true").contains("after value"));
+        assert!(err("sweep = 5\nsweep = 6").contains("twice"));
+        assert!(err("older_than = \"5w\"").contains("line 1"));
+    }
+
+    #[test]
+    fn test_from_env() {
+        let vars = [("DSK_FORCE", "yes"), ("DSK_EXCLUDE", "a, b,,c"), ("DSK_MIN_SIZE", "1k")];
+        let get = |k: &str| vars.iter().find(|(n, _)| *n == k).map(|(_, v)| v.to_string());
+        let layer = Layer::from_env(get).unwrap();
+        assert_eq!(layer.force, Some(true));
+        assert_eq!(layer.exclude, vec!["a", "b", "c"]);
+        assert_eq!(layer.min_size, Some(1024));
+
+        let bad = Layer::from_env(|k| (k == "DSK_NOTIFY").then(|| "maybe".into()));
+        assert!(bad.unwrap_err().starts_with("DSK_NOTIFY"));
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let source = |name: &str, text: &str| Source { name: name.into(), layer: Layer::parse(text).unwrap() };
+        let sources = [
+            source("user", "force = true\nsweep = 60\nexclude = [\"a\"]"),
+            source("local", "force = false\nexclude = [\"b\", \"a\"]"),
+            source("flags", "sweep = 5"),
+        ];
+        let merged = merge(&sources);
+        assert_eq!(merged.force, Some(false));
+        assert_eq!(merged.sweep, Some(5));
+        assert_eq!(merged.exclude, vec!["a", "b"]);
+        assert_eq!(merged.notify, None);
+
+        let shown = describe(&sources);
+        assert!(shown.lines().any(|l| l.starts_with("force = false") && l.ends_with("# local")));
+        assert!(shown.lines().any(|l| l.starts_with("exclude = [\"a\", \"b\"]") && l.ends_with("# user, local")));
+        assert!(shown.lines().any(|l| l.starts_with("debounce = 300") && l.ends_with("# default")));
+        assert!(shown.contains("# older_than is not set"));
+    }
+
+    #[test]
+    fn test_local_file_lookup() {
+        let dir = TempDir::new().unwrap();
+        let sub = dir.path().join("a/b");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(dir.path().join("a").join(LOCAL_FILE), "recursive = true\nforce = true").unwrap();
+
+        assert_eq!(local_file(&sub), Some(dir.path().join("a").join(LOCAL_FILE)));
+
+        let missing = dir.path().join("nope.toml");
+        let err = sources(Some(&missing), &sub, Layer::default()).unwrap_err();
+        assert!(err.contains("nope.toml"));
+
+        let user = dir.path().join("config.toml");
+        fs::write(&user, "recursive = false\nnotify = true").unwrap();
+        let flags = Layer { exclude: vec!["x".into()], ..Layer::default() };
+        let merged = resolve(Some(&user), &sub, flags).unwrap();
+        assert_eq!(merged.recursive, Some(true));
+        assert_eq!(merged.notify, Some(true));
+        assert_eq!(merged.exclude, vec!["x"]);
+        // Per-directory files can't turn on force
+        assert_eq!(merged.force, None);
+
+        // Only the first watch root's file applies
+        let other = dir.path().join("c");
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join(LOCAL_FILE), "notify = true").unwrap();
+        let roots = vec![sub.clone(), dir.path().join("a"), other.clone()];
+        assert_eq!(ignored_local_files(&roots), vec![other.join(LOCAL_FILE)]);
+        assert!(ignored_local_files(&roots[..2]).is_empty());
+    }
+}
@@ -6,6 +6,26 @@ pub const APP_NAME: &str = "dsk";
 /// Target filename to kill
 pub const TARGET_FILE: &str = ".DS_Store";
 
+/// Resource fork folder created by Archive Utility
+pub const MACOSX_DIR: &str = "__MACOSX";
+
+/// Name prefix of AppleDouble (resource fork / extended attribute) files
+pub const APPLE_DOUBLE_PREFIX: &str = "._";
+
+/// Virtual, device or duplicate file systems a walk never enters
+///
+/// `/System/Volumes` holds the firmlinked data volume, so walking `/` would
+/// otherwise see every user file twice.
+pub const PSEUDO_FS: [&str; 7] = [
+    "/dev",
+    "/proc",
+    "/sys",
+    "/net",
+    "/.vol",
+    "/System/Volumes",
+    "/private/var/vm",
+];
+
 /// launchd service identifier
 pub const SERVICE_ID: &str = "com.dsk.guard";
 
@@ -13,5 +33,42 @@ pub const SERVICE_ID: &str = "com.dsk.guard";
 pub const LOG_STDOUT: &str = "/tmp/dsk.out.log";
 pub const LOG_STDERR: &str = "/tmp/dsk.err.log";
 
+/// Persistent state (watch checkpoints)
+pub const STATE_DIR: &str = "~/Library/Caches/dsk";
+
+/// Default control socket of a running watcher
+pub const CTL_SOCKET: &str = "~/Library/Caches/dsk/ctl.sock";
+
+/// Default watch debounce window in milliseconds
+pub const DEBOUNCE_MS: u64 = 300;
+
+/// Maximum number of paths waiting out their debounce window
+pub const DEBOUNCE_CAPACITY: usize = 256;
+
+/// Maximum number of too-new targets waiting to be retried in watch mode
+pub const DEFERRED_CAPACITY: usize = 4096;
+
+/// How often watched roots are probed for disappearance/reappearance
+pub const ROOT_CHECK_SECS: u64 = 10;
+
+/// Default polling interval for roots on network/FUSE file systems
+pub const POLL_SECS: u64 = 5;
+
+/// Upper bound for `--jobs`, per CPU; more workers only add contention
+pub const MAX_JOBS_PER_CPU: usize = 4;
+
 /// launchd plist filename
 pub const PLIST_FILENAME: &str = "com.dsk.guard.plist";
+
+/// Process exit codes (see README "Exit Codes")
+pub const EXIT_OK: i32 = 0;
+/// Invalid arguments, unreadable target or service failure
+pub const EXIT_ERROR: i32 = 1;
+/// Dry-run found targets that would be deleted
+pub const EXIT_FOUND: i32 = 2;
+/// Some targets were left in place because they are git-tracked
+pub const EXIT_GIT_SKIPPED: i32 = 3;
+/// Some deletions failed
+pub const EXIT_PARTIAL: i32 = 4;
+/// User declined the confirmation prompt
+pub const EXIT_CANCELLED: i32 = 130;
@@ -0,0 +1,241 @@
+//! Control socket for a running watcher
+//!
+//! The watcher listens on a Unix domain socket; `dsk ctl` connects, sends one
+//! request line and reads one reply line. Replies are `ok <body>` or
+//! `err <message>`, where the body of `stats` is a JSON object.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// How long either side waits for the other before giving up
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A control request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Stats,
+    Pause,
+    Resume,
+    Rescan(PathBuf),
+    AddRoot(PathBuf),
+    RemoveRoot(PathBuf),
+    /// Leave a subtree alone for a while
+    PausePath(PathBuf, Duration),
+    ResumePath(PathBuf),
+}
+
+/// Reply body on success, message on failure
+pub type Reply = Result<String, String>;
+
+impl Request {
+    /// Encode as a single request line (without newline)
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Stats => "stats".into(),
+            Self::Pause => "pause".into(),
+            Self::Resume => "resume".into(),
+            Self::Rescan(p) => format!("rescan {}", p.display()),
+            Self::AddRoot(p) => format!("add-root {}", p.display()),
+            Self::RemoveRoot(p) => format!("remove-root {}", p.display()),
+            Self::PausePath(p, d) => format!("pause-path {} {}", d.as_secs(), p.display()),
+            Self::ResumePath(p) => format!("resume-path {}", p.display()),
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (cmd, arg) = match line.split_once(' ') {
+            Some((cmd, arg)) => (cmd, Some(PathBuf::from(arg))),
+            None => (line, None),
+        };
+        match (cmd, arg) {
+            ("stats", None) => Ok(Self::Stats),
+            ("pause", None) => Ok(Self::Pause),
+            ("resume", None) => Ok(Self::Resume),
+            ("rescan", Some(p)) => Ok(Self::Rescan(p)),
+            ("add-root", Some(p)) => Ok(Self::AddRoot(p)),
+            ("remove-root", Some(p)) => Ok(Self::RemoveRoot(p)),
+            ("resume-path", Some(p)) => Ok(Self::ResumePath(p)),
+            ("pause-path", Some(arg)) => {
+                let arg = arg.to_string_lossy();
+                let (secs, path) = arg.split_once(' ').ok_or("pause-path needs SECS PATH")?;
+                let secs = secs.parse().map_err(|_| format!("invalid seconds: {}", secs))?;
+                Ok(Self::PausePath(path.into(), Duration::from_secs(secs)))
+            }
+            _ => Err(format!("Unknown request: {}", line)),
+        }
+    }
+}
+
+/// A listening control socket; the socket file is removed on drop
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Listen on `path`, answering each request with `handle` on a background
+/// thread per connection
+///
+/// Fails if another watcher is already serving the same socket. A stale
+/// socket file left by a crashed watcher is replaced.
+pub fn serve<F>(path: &Path, handle: F) -> Result<Server, String>
+where
+    F: Fn(Request) -> Reply + Send + Sync + 'static,
+{
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("another watcher is listening on {}", path.display()));
+        }
+        fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+    let listener = bind(path).map_err(|e| e.to_string())?;
+
+    let handle = Arc::new(handle);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A slow or idle client must not hold up the others
+            let handle = handle.clone();
+            thread::spawn(move || answer(stream, &*handle));
+        }
+    });
+
+    Ok(Server { path: path.to_path_buf() })
+}
+
+/// Bind a socket at `path` that only the owning user can connect to
+///
+/// The socket is bound inside a private (0700) directory, restricted, and
+/// only then moved into place, so there is no moment where others could
+/// connect to it.
+fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staging = parent.join(format!(".{}.{}", name, process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let tmp = staging.join("sock");
+    let bound = UnixListener::bind(&tmp).and_then(|listener| {
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    bound
+}
+
+/// Read one request from `stream` and write back the reply
+fn answer<F: Fn(Request) -> Reply>(stream: UnixStream, handle: &F) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let reply = Request::parse(line.trim_end_matches('\n')).and_then(handle);
+    let mut stream = stream;
+    match reply {
+        Ok(body) => writeln!(stream, "ok {}", body),
+        Err(msg) => writeln!(stream, "err {}", msg),
+    }
+}
+
+/// Send `request` to the watcher listening on `path` and wait for its reply
+pub fn request(path: &Path, request: &Request) -> Reply {
+    let mut stream = UnixStream::connect(path)
+        .map_err(|e| format!("No watcher is listening on {} ({})", path.display(), e))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    writeln!(stream, "{}", request.to_line()).map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    let line = line.trim_end_matches('\n');
+
+    if let Some(body) = line.strip_prefix("ok ") {
+        Ok(body.to_string())
+    } else if let Some(msg) = line.strip_prefix("err ") {
+        Err(msg.to_string())
+    } else {
+        Err("Malformed reply from watcher".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_request_round_trip() {
+        let requests = [
+            Request::Stats,
+            Request::Pause,
+            Request::Resume,
+            Request::Rescan("/a b/c".into()),
+            Request::AddRoot("/x".into()),
+            Request::RemoveRoot("/y".into()),
+            Request::PausePath("/d m/g".into(), Duration::from_secs(1800)),
+            Request::ResumePath("/d m/g".into()),
+        ];
+        for r in requests {
+            assert_eq!(Request::parse(&r.to_line()), Ok(r));
+        }
+        assert!(Request::parse("stats now").is_err());
+        assert!(Request::parse("rescan").is_err());
+        assert!(Request::parse("reboot").is_err());
+        assert!(Request::parse("pause-path soon /x").is_err());
+    }
+
+    #[test]
+    fn test_serve_and_request() {
+        let dir = TempDir::new().unwrap();
+        let sock = dir.path().join("run/ctl.sock");
+
+        let server = serve(&sock, |req| match req {
+            Request::Stats => Ok(r#"{"deleted":3}"#.into()),
+            Request::Rescan(p) => Err(format!("not watched: {}", p.display())),
+            _ => Ok("done".into()),
+        })
+        .unwrap();
+
+        assert_eq!(request(&sock, &Request::Stats), Ok(r#"{"deleted":3}"#.into()));
+        assert_eq!(request(&sock, &Request::Pause), Ok("done".into()));
+        assert_eq!(
+            request(&sock, &Request::Rescan("/z".into())),
+            Err("not watched: /z".into())
+        );
+
+        // Only the owner may connect
+        let mode = fs::metadata(&sock).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // An idle client doesn't block the others
+        let _idle = UnixStream::connect(&sock).unwrap();
+        assert_eq!(request(&sock, &Request::Pause), Ok("done".into()));
+
+        // A second server on a live socket is refused
+        assert!(serve(&sock, |_| Ok(String::new())).is_err());
+
+        drop(server);
+        assert!(!sock.exists());
+        assert!(request(&sock, &Request::Stats).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Per-path event coalescing for watch mode
+//!
+//! Finder tends to rewrite a `.DS_Store` several times in a burst. Instead of
+//! acting on every event, paths are held until they have been quiet for the
+//! debounce delay, so each burst results in a single git check and delete.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Coalesces repeated events per path until the path goes quiet
+pub struct Debouncer {
+    delay: Duration,
+    capacity: usize,
+    /// Path -> time of the most recent event
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration, capacity: usize) -> Self {
+        Self {
+            delay,
+            capacity: capacity.max(1),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Record an event for `path` at `now`
+    ///
+    /// Repeated events for a pending path only push its deadline back. When
+    /// the queue is full, the longest-waiting path is evicted and returned so
+    /// the caller can handle it right away instead of dropping it.
+    pub fn push(&mut self, path: PathBuf, now: Instant) -> Option<PathBuf> {
+        if let Some(seen) = self.pending.get_mut(&path) {
+            *seen = now;
+            return None;
+        }
+
+        let evicted = if self.pending.len() >= self.capacity {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, t)| **t)
+                .map(|(p, _)| p.clone());
+            oldest.inspect(|p| {
+                self.pending.remove(p);
+            })
+        } else {
+            None
+        };
+
+        self.pending.insert(path, now);
+        evicted
+    }
+
+    /// Forget a pending path and anything pending beneath it (e.g. it was
+    /// removed or renamed away)
+    pub fn cancel(&mut self, path: &Path) {
+        self.pending.retain(|p, _| !p.starts_with(path));
+    }
+
+    /// Remove and return paths that have been quiet for at least the delay
+    pub fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready: Vec<(PathBuf, Instant)> = Vec::new();
+        self.pending.retain(|p, t| {
+            if now.saturating_duration_since(*t) >= self.delay {
+                ready.push((p.clone(), *t));
+                false
+            } else {
+                true
+            }
+        });
+        ready.sort_by_key(|(_, t)| *t);
+        ready.into_iter().map(|(p, _)| p).collect()
+    }
+
+    /// Remove and return every pending path, oldest first
+    pub fn drain(&mut self) -> Vec<PathBuf> {
+        let mut all: Vec<(PathBuf, Instant)> = self.pending.drain().collect();
+        all.sort_by_key(|(_, t)| *t);
+        all.into_iter().map(|(p, _)| p).collect()
+    }
+
+    /// Number of pending paths
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Earliest instant at which a pending path becomes ready
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().min().map(|t| *t + self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_coalesces_burst() {
+        let mut d = Debouncer::new(DELAY, 8);
+        let t0 = Instant::now();
+        let p = PathBuf::from("/a/.DS_Store");
+
+        for i in 0..5 {
+            assert!(d.push(p.clone(), t0 + Duration::from_millis(i * 10)).is_none());
+        }
+        assert_eq!(d.pending.len(), 1);
+
+        // Quiet period counts from the last event (t0 + 40ms)
+        assert!(d.ready(t0 + Duration::from_millis(120)).is_empty());
+        assert_eq!(d.next_deadline(), Some(t0 + Duration::from_millis(140)));
+        assert_eq!(d.ready(t0 + Duration::from_millis(140)), vec![p]);
+        assert!(d.pending.is_empty());
+    }
+
+    #[test]
+    fn test_ready_in_event_order() {
+        let mut d = Debouncer::new(DELAY, 8);
+        let t0 = Instant::now();
+        d.push(PathBuf::from("/b"), t0 + Duration::from_millis(5));
+        d.push(PathBuf::from("/a"), t0);
+
+        let ready = d.ready(t0 + DELAY * 2);
+        assert_eq!(ready, vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+    }
+
+    #[test]
+    fn test_cancel_subtree() {
+        let mut d = Debouncer::new(DELAY, 8);
+        let t0 = Instant::now();
+        d.push(PathBuf::from("/a/.DS_Store"), t0);
+        d.push(PathBuf::from("/a/b/.DS_Store"), t0);
+        d.push(PathBuf::from("/ab/.DS_Store"), t0);
+
+        d.cancel(Path::new("/a"));
+        assert_eq!(d.drain(), vec![PathBuf::from("/ab/.DS_Store")]);
+    }
+
+    #[test]
+    fn test_bounded_evicts_oldest() {
+        let mut d = Debouncer::new(DELAY, 2);
+        let t0 = Instant::now();
+        assert!(d.push(PathBuf::from("/1"), t0).is_none());
+        assert!(d.push(PathBuf::from("/2"), t0 + Duration::from_millis(1)).is_none());
+
+        let evicted = d.push(PathBuf::from("/3"), t0 + Duration::from_millis(2));
+        assert_eq!(evicted, Some(PathBuf::from("/1")));
+        assert_eq!(d.pending.len(), 2);
+
+        assert_eq!(d.drain(), vec![PathBuf::from("/2"), PathBuf::from("/3")]);
+    }
+}
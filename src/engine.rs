@@ -0,0 +1,885 @@
+//! Watch-mode state machine, decoupled from notify
+//!
+//! The engine consumes abstract [`FsEvent`]s with explicit timestamps and
+//! answers with [`Action`]s for the driver to carry out. It performs no I/O,
+//! so event sequences can be replayed deterministically in tests.
+
+use crate::{
+    consts::{DEBOUNCE_CAPACITY, DEFERRED_CAPACITY},
+    debounce::Debouncer,
+    killer,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Backend-independent file system event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsEvent {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+    Removed(PathBuf),
+    /// A directory appeared, either created or moved/extracted into the tree
+    DirCreated(PathBuf),
+    /// Backend dropped events (queue overflow); the tree must be rescanned
+    Rescan,
+    /// Backend reported an error
+    Error(String),
+}
+
+/// Work the driver must perform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Run exclude/git checks on a target and delete it
+    Handle(PathBuf),
+    /// Scan a subtree for targets, each then handled like `Handle`
+    Scan(PathBuf),
+    /// Probe every root and report back via [`Engine::root_status`]
+    CheckRoots,
+    /// Register a root that reappeared with the backend
+    Watch(PathBuf),
+    /// Drop the backend registration of a root that disappeared
+    Unwatch(PathBuf),
+    /// Report a backend error
+    Error(String),
+}
+
+/// What happened when the driver handled a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Deleted,
+    SkippedGit,
+    Excluded,
+    /// Left in place by a protection rule
+    Protected,
+    /// Left in place by an age or size filter
+    Filtered,
+    /// Left in place by `--older-than` for now; old enough after this long
+    TooNew(Duration),
+    Failed,
+    /// Vanished before it could be handled
+    Gone,
+}
+
+/// Counters accumulated over the lifetime of a watcher
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchStats {
+    pub events: u64,
+    pub deleted: u64,
+    pub skipped_git: u64,
+    pub excluded: u64,
+    pub protected: u64,
+    pub filtered: u64,
+    pub errors: u64,
+    /// Full rescans triggered by overflow or backend errors
+    pub rescans: u64,
+}
+
+impl fmt::Display for WatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} deleted, {} skipped (git), {} excluded, {} protected, {} filtered, {} error(s)",
+            self.deleted,
+            self.skipped_git,
+            self.excluded,
+            self.protected,
+            self.filtered,
+            self.errors
+        )
+    }
+}
+
+/// A recurring deadline
+struct Timer {
+    every: Duration,
+    next: Instant,
+}
+
+impl Timer {
+    fn new(every: Duration, now: Instant) -> Self {
+        Self { every, next: now + every }
+    }
+
+    /// Whether the timer fired at `now`; re-arms it if so
+    fn fire(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next = now + self.every;
+        true
+    }
+}
+
+/// A watched root and whether it is currently present
+struct Root {
+    path: PathBuf,
+    up: bool,
+}
+
+/// Pure event-handling core of watch mode
+pub struct Engine {
+    debounce: Duration,
+    pending: Debouncer,
+    /// New directories waiting for the tree to settle before a sub-scan
+    scans: Debouncer,
+    roots: Vec<Root>,
+    /// Debounced full rescan after overflow or backend errors
+    rescan_at: Option<Instant>,
+    health: Option<Timer>,
+    sweep: Option<Timer>,
+    /// Events are ignored until resumed
+    paused: bool,
+    /// Subtrees left alone until the given instant
+    paused_paths: Vec<(PathBuf, Instant)>,
+    /// Targets too new to delete yet, retried at the given instant; at most
+    /// [`DEFERRED_CAPACITY`], the rest wait for the next event or sweep
+    deferred: HashMap<PathBuf, Instant>,
+    /// Walk limits, with depths counted from the owning root
+    traversal: killer::Traversal,
+    stats: WatchStats,
+}
+
+impl Engine {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: Debouncer::new(debounce, DEBOUNCE_CAPACITY),
+            scans: Debouncer::new(debounce, DEBOUNCE_CAPACITY),
+            roots: Vec::new(),
+            rescan_at: None,
+            health: None,
+            sweep: None,
+            paused: false,
+            paused_paths: Vec::new(),
+            deferred: HashMap::new(),
+            traversal: killer::Traversal::default(),
+            stats: WatchStats::default(),
+        }
+    }
+
+    /// Watched roots, probed every `check_every` and rescanned on overflow
+    pub fn with_roots(mut self, roots: &[PathBuf], check_every: Duration, now: Instant) -> Self {
+        self.roots = roots.iter().map(|p| Root { path: p.clone(), up: true }).collect();
+        self.health = Some(Timer::new(check_every, now));
+        self
+    }
+
+    /// Limit scans, and depth-limit events below each root
+    pub fn with_traversal(mut self, traversal: killer::Traversal) -> Self {
+        self.traversal = traversal;
+        self
+    }
+
+    /// Depth of `path` below the root containing it; a root's own entries
+    /// are at depth 1
+    fn depth(&self, path: &Path) -> Option<usize> {
+        self.roots
+            .iter()
+            .filter_map(|r| path.strip_prefix(&r.path).ok())
+            .map(|rel| rel.components().count())
+            .min()
+    }
+
+    /// Whether a target at `path` is within the depth limits
+    pub fn in_depth(&self, path: &Path) -> bool {
+        self.depth(path).is_none_or(|d| self.traversal.admits(d))
+    }
+
+    /// Walk limits for a scan of `dir`, relative to `dir`
+    pub fn traversal_at(&self, dir: &Path) -> killer::Traversal {
+        self.traversal.below(self.depth(dir).unwrap_or(0))
+    }
+
+    /// Rescan every root periodically, as a safety net for missed events
+    pub fn with_sweep(mut self, every: Duration, now: Instant) -> Self {
+        self.sweep = Some(Timer::new(every, now));
+        self
+    }
+
+    fn live_roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().filter(|r| r.up).map(|r| r.path.clone()).collect()
+    }
+
+    fn queue(&mut self, path: PathBuf, now: Instant, actions: &mut Vec<Action>) {
+        if killer::is_target(&path)
+            && self.in_depth(&path)
+            && let Some(evicted) = self.pending.push(path, now)
+        {
+            actions.push(Action::Handle(evicted));
+        }
+    }
+
+    /// Whether `path` lies under a configured root
+    ///
+    /// An engine built without [`Engine::with_roots`] accepts every path.
+    fn in_roots(&self, path: &Path) -> bool {
+        self.health.is_none() || self.roots.iter().any(|r| path.starts_with(&r.path))
+    }
+
+    /// Feed one event observed at `now`
+    pub fn on_event(&mut self, event: FsEvent, now: Instant) -> Vec<Action> {
+        self.stats.events += 1;
+        let mut actions = Vec::new();
+
+        // Late events for a removed root, or anything while paused
+        let relevant = match &event {
+            FsEvent::Removed(p) => self.in_roots(p),
+            FsEvent::Created(p)
+            | FsEvent::Modified(p)
+            | FsEvent::DirCreated(p)
+            | FsEvent::Renamed { to: p, .. } => self.in_roots(p) && !self.is_paused_at(p),
+            FsEvent::Rescan | FsEvent::Error(_) => true,
+        };
+        if !relevant || (self.paused && !matches!(event, FsEvent::Error(_))) {
+            return actions;
+        }
+
+        match event {
+            FsEvent::Created(path) | FsEvent::Modified(path) => {
+                self.queue(path, now, &mut actions);
+            }
+            FsEvent::Renamed { from, to } => {
+                self.pending.cancel(&from);
+                self.scans.cancel(&from);
+                self.deferred.retain(|p, _| !p.starts_with(&from));
+                self.queue(to, now, &mut actions);
+            }
+            FsEvent::Removed(path) => {
+                self.pending.cancel(&path);
+                self.scans.cancel(&path);
+                self.deferred.retain(|p, _| !p.starts_with(&path));
+            }
+            FsEvent::DirCreated(dir) => {
+                // Nothing inside can be shallow enough
+                if self.traversal_at(&dir).max_depth == Some(0) {
+                    return actions;
+                }
+                if let Some(evicted) = self.scans.push(dir, now) {
+                    actions.push(Action::Scan(evicted));
+                }
+            }
+            FsEvent::Rescan => self.rescan_at = Some(now + self.debounce),
+            FsEvent::Error(msg) => {
+                // Events may have been lost along with the error
+                self.stats.errors += 1;
+                self.rescan_at = Some(now + self.debounce);
+                actions.push(Action::Error(msg));
+            }
+        }
+
+        actions
+    }
+
+    /// Report whether a root is present; returns re-registration work when
+    /// a root comes back, or deregistration when it disappears
+    pub fn root_status(&mut self, path: &Path, up: bool) -> Vec<Action> {
+        let Some(root) = self.roots.iter_mut().find(|r| r.path == path) else {
+            return Vec::new();
+        };
+
+        match (root.up, up) {
+            (true, false) => {
+                root.up = false;
+                self.pending.cancel(path);
+                self.scans.cancel(path);
+                vec![Action::Unwatch(path.to_path_buf())]
+            }
+            (false, true) => {
+                root.up = true;
+                vec![Action::Watch(path.to_path_buf()), Action::Scan(path.to_path_buf())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Build actions for released targets and directories
+    ///
+    /// Directories nested inside another released directory are dropped
+    /// (the outer scan covers them), as are targets inside a scanned one.
+    fn release(targets: Vec<PathBuf>, all_dirs: Vec<PathBuf>) -> Vec<Action> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for d in all_dirs {
+            if !dirs.contains(&d) {
+                dirs.push(d);
+            }
+        }
+
+        let covered = |p: &PathBuf, skip_self: bool| {
+            dirs.iter().any(|d| p.starts_with(d) && !(skip_self && p == d))
+        };
+
+        let scans = dirs.iter().filter(|d| !covered(d, true)).cloned().map(Action::Scan);
+        let handles = targets.into_iter().filter(|t| !covered(t, false)).map(Action::Handle);
+        handles.chain(scans).collect()
+    }
+
+    /// Release work whose time has come: debounced targets and
+    /// directories, overflow rescans, root health checks and sweeps
+    pub fn on_tick(&mut self, now: Instant) -> Vec<Action> {
+        if self.paused {
+            return self.check_health(now);
+        }
+
+        let mut dirs = self.scans.ready(now);
+        dirs.extend(self.expire_pauses(now));
+
+        let rescan = self.rescan_at.is_some_and(|t| t <= now);
+        if rescan {
+            self.rescan_at = None;
+            self.stats.rescans += 1;
+        }
+        let sweep = self.sweep.as_mut().is_some_and(|t| t.fire(now));
+        if rescan || sweep {
+            dirs.extend(self.live_roots());
+        }
+
+        let mut targets = self.pending.ready(now);
+        let mut due = Vec::new();
+        self.deferred.retain(|p, at| {
+            let ready = *at <= now;
+            if ready {
+                due.push(p.clone());
+            }
+            !ready
+        });
+        let debounced: HashSet<PathBuf> = targets.iter().cloned().collect();
+        targets.extend(due.into_iter().filter(|p| !debounced.contains(p)));
+        let mut actions = Self::release(targets, dirs);
+        actions.extend(self.check_health(now));
+        actions
+    }
+
+    fn check_health(&mut self, now: Instant) -> Vec<Action> {
+        if self.health.as_mut().is_some_and(|t| t.fire(now)) {
+            vec![Action::CheckRoots]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// When the next piece of work becomes due, if any is scheduled
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.paused {
+            return self.health.as_ref().map(|t| t.next);
+        }
+        [
+            self.pending.next_deadline(),
+            self.scans.next_deadline(),
+            self.rescan_at,
+            self.health.as_ref().map(|t| t.next),
+            self.sweep.as_ref().map(|t| t.next),
+            self.paused_paths.iter().map(|(_, until)| *until).min(),
+            self.deferred.values().min().copied(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Paths of all configured roots
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|r| r.path.clone()).collect()
+    }
+
+    /// Roots with whether each is currently present
+    pub fn root_states(&self) -> Vec<(PathBuf, bool)> {
+        self.roots.iter().map(|r| (r.path.clone(), r.up)).collect()
+    }
+
+    /// Start watching another root
+    ///
+    /// Refused when it overlaps an existing root, since nested recursive
+    /// watches would report every event twice.
+    pub fn add_root(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(r) = self
+            .roots
+            .iter()
+            .find(|r| path.starts_with(&r.path) || r.path.starts_with(path))
+        {
+            return Err(format!("overlaps watched root {}", r.path.display()));
+        }
+        self.roots.push(Root { path: path.to_path_buf(), up: true });
+        Ok(())
+    }
+
+    /// Stop watching a root and forget its pending work; false if it isn't one
+    pub fn remove_root(&mut self, path: &Path) -> bool {
+        let before = self.roots.len();
+        self.roots.retain(|r| r.path != path);
+        self.pending.cancel(path);
+        self.scans.cancel(path);
+        self.deferred.retain(|p, _| !p.starts_with(path));
+        self.roots.len() != before
+    }
+
+    /// Scan a directory inside a live root right away
+    pub fn rescan(&mut self, dir: &Path) -> Result<Vec<Action>, String> {
+        if self.paused {
+            return Err("watcher is paused".into());
+        }
+        if !self.live_roots().iter().any(|r| dir.starts_with(r)) {
+            return Err(format!("not inside a watched root: {}", dir.display()));
+        }
+        self.scans.cancel(dir);
+        self.stats.rescans += 1;
+        Ok(vec![Action::Scan(dir.to_path_buf())])
+    }
+
+    /// Stop acting on events everywhere
+    ///
+    /// Pending work is dropped; [`Engine::resume`] rescans every root
+    /// instead, which also catches whatever appeared while paused.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.pending.drain();
+        self.scans.drain();
+        self.rescan_at = None;
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if self.paused {
+            self.paused = false;
+            self.rescan_at = Some(now);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Leave targets under `path` alone until `until`
+    ///
+    /// Replaces an existing pause of the same path. Pending work under it is
+    /// dropped; the subtree is rescanned when the pause ends.
+    pub fn pause_path(&mut self, path: &Path, until: Instant) {
+        self.paused_paths.retain(|(p, _)| p != path);
+        self.paused_paths.push((path.to_path_buf(), until));
+        self.pending.cancel(path);
+        self.scans.cancel(path);
+    }
+
+    /// End a pause early; the subtree is rescanned on the next tick
+    pub fn resume_path(&mut self, path: &Path, now: Instant) -> bool {
+        match self.paused_paths.iter_mut().find(|(p, _)| p == path) {
+            Some(entry) => {
+                entry.1 = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether targets at `path` are currently left alone
+    pub fn is_paused_at(&self, path: &Path) -> bool {
+        self.paused || self.paused_paths.iter().any(|(p, _)| path.starts_with(p))
+    }
+
+    /// Subtree pauses with their time left
+    pub fn paused_paths(&self, now: Instant) -> Vec<(PathBuf, Duration)> {
+        self.paused_paths
+            .iter()
+            .map(|(p, until)| (p.clone(), until.saturating_duration_since(now)))
+            .collect()
+    }
+
+    /// Drop pauses that ended, returning what must be rescanned: the paused
+    /// directory, or the roots inside it when it spans whole roots
+    fn expire_pauses(&mut self, now: Instant) -> Vec<PathBuf> {
+        let (ended, active): (Vec<_>, Vec<_>) =
+            self.paused_paths.drain(..).partition(|(_, until)| *until <= now);
+        self.paused_paths = active;
+
+        let mut dirs = Vec::new();
+        for (path, _) in ended {
+            for root in self.live_roots() {
+                if path.starts_with(&root) {
+                    dirs.push(path.clone());
+                } else if root.starts_with(&path) {
+                    dirs.push(root);
+                }
+            }
+        }
+        dirs
+    }
+
+    /// Targets and directories waiting out their debounce window
+    pub fn queued(&self) -> usize {
+        self.pending.len() + self.scans.len()
+    }
+
+    /// Flush everything pending regardless of its debounce window
+    pub fn shutdown(&mut self) -> Vec<Action> {
+        Self::release(self.pending.drain(), self.scans.drain())
+    }
+
+    /// Account for the outcome of a handled target; one that is too new is
+    /// retried once it is old enough, and only counted once it is final
+    pub fn record(&mut self, path: &Path, outcome: Outcome, now: Instant) {
+        match outcome {
+            Outcome::TooNew(wait) => {
+                if self.deferred.len() < DEFERRED_CAPACITY || self.deferred.contains_key(path) {
+                    self.deferred.insert(path.to_path_buf(), now + wait);
+                }
+            }
+            Outcome::Deleted => self.stats.deleted += 1,
+            Outcome::SkippedGit => self.stats.skipped_git += 1,
+            Outcome::Excluded => self.stats.excluded += 1,
+            Outcome::Protected => self.stats.protected += 1,
+            Outcome::Filtered => self.stats.filtered += 1,
+            Outcome::Failed => self.stats.errors += 1,
+            Outcome::Gone => {}
+        }
+    }
+
+    pub fn stats(&self) -> &WatchStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    fn ms(t0: Instant, n: u64) -> Instant {
+        t0 + Duration::from_millis(n)
+    }
+
+    fn handle(p: &str) -> Action {
+        Action::Handle(PathBuf::from(p))
+    }
+
+    #[test]
+    fn test_create_modify_burst_handled_once() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        assert!(e.on_event(FsEvent::Created("/a/.DS_Store".into()), t0).is_empty());
+        assert!(e.on_event(FsEvent::Modified("/a/.DS_Store".into()), ms(t0, 20)).is_empty());
+        assert!(e.on_event(FsEvent::Modified("/a/notes.txt".into()), ms(t0, 30)).is_empty());
+
+        assert!(e.on_tick(ms(t0, 100)).is_empty());
+        assert_eq!(e.on_tick(ms(t0, 120)), vec![handle("/a/.DS_Store")]);
+        assert!(e.on_tick(ms(t0, 500)).is_empty());
+        assert_eq!(e.stats().events, 3);
+    }
+
+    #[test]
+    fn test_remove_cancels_pending() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        e.on_event(FsEvent::Created("/a/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Removed("/a/.DS_Store".into()), ms(t0, 10));
+
+        assert_eq!(e.next_deadline(), None);
+        assert!(e.on_tick(ms(t0, 500)).is_empty());
+    }
+
+    #[test]
+    fn test_rename_follows_target() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        // Finder writes a temp file and renames it over .DS_Store
+        e.on_event(
+            FsEvent::Renamed { from: "/a/.DS_Store.tmp".into(), to: "/a/.DS_Store".into() },
+            t0,
+        );
+        // A pending target moved elsewhere is only handled at its new path
+        e.on_event(FsEvent::Created("/b/.DS_Store".into()), t0);
+        e.on_event(
+            FsEvent::Renamed { from: "/b/.DS_Store".into(), to: "/c/.DS_Store".into() },
+            ms(t0, 10),
+        );
+        // Renaming a target to a non-target name drops it
+        e.on_event(FsEvent::Created("/d/.DS_Store".into()), t0);
+        e.on_event(
+            FsEvent::Renamed { from: "/d/.DS_Store".into(), to: "/d/keep".into() },
+            ms(t0, 10),
+        );
+
+        assert_eq!(
+            e.on_tick(ms(t0, 200)),
+            vec![handle("/a/.DS_Store"), handle("/c/.DS_Store")]
+        );
+    }
+
+    #[test]
+    fn test_directory_moved_in_is_scanned() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        // Extracting an archive: the top directory, a nested one, and a
+        // target inside it all show up in one burst
+        e.on_event(FsEvent::DirCreated("/w/pkg".into()), t0);
+        e.on_event(FsEvent::DirCreated("/w/pkg/sub".into()), ms(t0, 5));
+        e.on_event(FsEvent::Created("/w/pkg/sub/.DS_Store".into()), ms(t0, 6));
+        e.on_event(FsEvent::DirCreated("/w/other".into()), ms(t0, 7));
+        e.on_event(FsEvent::Created("/w/.DS_Store".into()), ms(t0, 8));
+
+        assert_eq!(
+            e.on_tick(ms(t0, 200)),
+            vec![
+                handle("/w/.DS_Store"),
+                Action::Scan("/w/pkg".into()),
+                Action::Scan("/w/other".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_moved_away_cancels_scan() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        e.on_event(FsEvent::DirCreated("/w/tmp".into()), t0);
+        e.on_event(FsEvent::Created("/w/tmp/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Removed("/w/tmp".into()), ms(t0, 10));
+
+        assert_eq!(e.next_deadline(), None);
+        assert!(e.shutdown().is_empty());
+    }
+
+    #[test]
+    fn test_overflow_triggers_single_rescan() {
+        let t0 = Instant::now();
+        let roots = vec![PathBuf::from("/r1"), PathBuf::from("/r2")];
+        let mut e = Engine::new(DEBOUNCE).with_roots(&roots, Duration::from_secs(3600), t0);
+
+        e.on_event(FsEvent::Created("/r1/a/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Rescan, t0);
+        e.on_event(FsEvent::Rescan, ms(t0, 10));
+        assert_eq!(e.next_deadline(), Some(ms(t0, 100)));
+
+        // The pending target is covered by the root scan
+        assert_eq!(
+            e.on_tick(ms(t0, 110)),
+            vec![Action::Scan("/r1".into()), Action::Scan("/r2".into())]
+        );
+        assert!(e.on_tick(ms(t0, 500)).is_empty());
+        assert_eq!(e.stats().rescans, 1);
+    }
+
+    #[test]
+    fn test_backend_error_triggers_rescan() {
+        let t0 = Instant::now();
+        let mut e = Engine::new(DEBOUNCE).with_roots(&["/r".into()], Duration::from_secs(3600), t0);
+
+        let actions = e.on_event(FsEvent::Error("lost".into()), t0);
+        assert_eq!(actions, vec![Action::Error("lost".into())]);
+        assert_eq!(e.on_tick(ms(t0, 100)), vec![Action::Scan("/r".into())]);
+    }
+
+    #[test]
+    fn test_root_disappears_and_returns() {
+        let t0 = Instant::now();
+        let roots = vec![PathBuf::from("/vol"), PathBuf::from("/home")];
+        let mut e = Engine::new(DEBOUNCE).with_roots(&roots, Duration::from_secs(5), t0);
+
+        assert!(e.on_tick(ms(t0, 1000)).is_empty());
+        assert_eq!(e.on_tick(ms(t0, 5000)), vec![Action::CheckRoots]);
+
+        assert_eq!(e.root_status(Path::new("/vol"), false), vec![Action::Unwatch("/vol".into())]);
+        assert!(e.root_status(Path::new("/vol"), false).is_empty());
+        assert!(e.root_status(Path::new("/home"), true).is_empty());
+
+        // Overflow while the volume is gone only rescans live roots
+        e.on_event(FsEvent::Rescan, ms(t0, 6000));
+        assert_eq!(e.on_tick(ms(t0, 6100)), vec![Action::Scan("/home".into())]);
+
+        assert_eq!(
+            e.root_status(Path::new("/vol"), true),
+            vec![Action::Watch("/vol".into()), Action::Scan("/vol".into())]
+        );
+    }
+
+    #[test]
+    fn test_periodic_sweep() {
+        let t0 = Instant::now();
+        let mut e = Engine::new(DEBOUNCE)
+            .with_roots(&["/r".into()], Duration::from_secs(3600), t0)
+            .with_sweep(Duration::from_secs(60), t0);
+
+        assert_eq!(e.next_deadline(), Some(t0 + Duration::from_secs(60)));
+        assert!(e.on_tick(t0 + Duration::from_secs(59)).is_empty());
+        assert_eq!(e.on_tick(t0 + Duration::from_secs(60)), vec![Action::Scan("/r".into())]);
+        assert_eq!(e.next_deadline(), Some(t0 + Duration::from_secs(120)));
+        assert_eq!(e.stats().rescans, 0);
+    }
+
+    #[test]
+    fn test_shutdown_flushes_and_stats() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+
+        e.on_event(FsEvent::Created("/a/.DS_Store".into()), t0);
+        let errs = e.on_event(FsEvent::Error("overflow".into()), t0);
+        assert_eq!(errs, vec![Action::Error("overflow".into())]);
+
+        assert_eq!(e.shutdown(), vec![handle("/a/.DS_Store")]);
+
+        let p = Path::new("/a/.DS_Store");
+        e.record(p, Outcome::Deleted, t0);
+        e.record(p, Outcome::SkippedGit, t0);
+        e.record(p, Outcome::Gone, t0);
+        e.record(p, Outcome::Protected, t0);
+        assert_eq!(
+            e.stats().to_string(),
+            "1 deleted, 1 skipped (git), 0 excluded, 1 protected, 0 filtered, 1 error(s)"
+        );
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let t0 = Instant::now();
+        let mut e = Engine::new(DEBOUNCE).with_roots(&["/r".into()], Duration::from_secs(3600), t0);
+
+        e.on_event(FsEvent::Created("/r/a/.DS_Store".into()), t0);
+        assert_eq!(e.queued(), 1);
+
+        e.pause();
+        assert_eq!(e.queued(), 0);
+        assert!(e.on_event(FsEvent::Created("/r/b/.DS_Store".into()), t0).is_empty());
+        assert!(e.on_tick(t0 + DEBOUNCE).is_empty());
+        assert!(e.rescan(Path::new("/r")).is_err());
+        assert_eq!(e.next_deadline(), Some(t0 + Duration::from_secs(3600)));
+
+        // Resuming catches up with a rescan of every root
+        let t1 = t0 + DEBOUNCE * 2;
+        e.resume(t1);
+        assert_eq!(e.on_tick(t1), vec![Action::Scan("/r".into())]);
+    }
+
+    #[test]
+    fn test_add_and_remove_roots() {
+        let t0 = Instant::now();
+        let mut e = Engine::new(DEBOUNCE).with_roots(&["/r".into()], Duration::from_secs(3600), t0);
+
+        assert!(e.add_root(Path::new("/r/sub")).is_err());
+        assert!(e.add_root(Path::new("/")).is_err());
+        e.add_root(Path::new("/s")).unwrap();
+        assert_eq!(e.roots(), vec![PathBuf::from("/r"), PathBuf::from("/s")]);
+
+        assert_eq!(e.rescan(Path::new("/s/x")), Ok(vec![Action::Scan("/s/x".into())]));
+        assert!(e.rescan(Path::new("/t")).is_err());
+
+        e.on_event(FsEvent::Created("/s/.DS_Store".into()), t0);
+        assert!(e.remove_root(Path::new("/s")));
+        assert!(!e.remove_root(Path::new("/s")));
+        assert_eq!(e.queued(), 0);
+
+        // Late events from the removed root are ignored
+        e.on_event(FsEvent::Created("/s/.DS_Store".into()), t0);
+        assert_eq!(e.queued(), 0);
+    }
+
+    #[test]
+    fn test_pause_subtree_until_expiry() {
+        let t0 = Instant::now();
+        let mut e = Engine::new(DEBOUNCE).with_roots(&["/r".into()], Duration::from_secs(3600), t0);
+
+        e.on_event(FsEvent::Created("/r/dmg/.DS_Store".into()), t0);
+        let until = t0 + Duration::from_secs(60);
+        e.pause_path(Path::new("/r/dmg"), until);
+        assert_eq!(e.queued(), 0);
+        assert!(e.is_paused_at(Path::new("/r/dmg/sub/.DS_Store")));
+        assert!(!e.is_paused_at(Path::new("/r/other/.DS_Store")));
+
+        // Events inside the subtree are ignored, others still handled
+        e.on_event(FsEvent::Created("/r/dmg/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Created("/r/other/.DS_Store".into()), t0);
+        assert_eq!(e.on_tick(t0 + DEBOUNCE), vec![handle("/r/other/.DS_Store")]);
+        assert_eq!(e.next_deadline(), Some(until));
+
+        // Expiry rescans the subtree
+        assert_eq!(e.on_tick(until), vec![Action::Scan("/r/dmg".into())]);
+        assert!(!e.is_paused_at(Path::new("/r/dmg/.DS_Store")));
+
+        // A pause spanning the root rescans the root when lifted early
+        e.pause_path(Path::new("/"), t0 + Duration::from_secs(600));
+        assert!(e.resume_path(Path::new("/"), until));
+        assert!(!e.resume_path(Path::new("/x"), until));
+        assert_eq!(e.on_tick(until), vec![Action::Scan("/r".into())]);
+    }
+
+    #[test]
+    fn test_depth_limits_relative_to_root() {
+        let t0 = Instant::now();
+        let traversal =
+            killer::Traversal { min_depth: Some(2), max_depth: Some(2), ..Default::default() };
+        let mut e = Engine::new(DEBOUNCE)
+            .with_roots(&["/r".into()], Duration::from_secs(3600), t0)
+            .with_traversal(traversal);
+
+        e.on_event(FsEvent::Created("/r/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Created("/r/a/.DS_Store".into()), t0);
+        e.on_event(FsEvent::Created("/r/a/b/.DS_Store".into()), t0);
+        // Nothing inside /r/a/b can be shallow enough to scan for
+        e.on_event(FsEvent::DirCreated("/r/a/b".into()), t0);
+        e.on_event(FsEvent::DirCreated("/r/c".into()), t0);
+        assert_eq!(
+            e.on_tick(t0 + DEBOUNCE),
+            vec![handle("/r/a/.DS_Store"), Action::Scan("/r/c".into())]
+        );
+
+        let below = e.traversal_at(Path::new("/r/c"));
+        assert_eq!((below.min_depth, below.max_depth), (Some(1), Some(1)));
+    }
+
+    #[test]
+    fn test_too_new_target_is_retried() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+        let wait = Duration::from_secs(600);
+
+        e.record(Path::new("/a/.DS_Store"), Outcome::TooNew(wait), t0);
+        e.record(Path::new("/b/.DS_Store"), Outcome::TooNew(wait), t0);
+        // Not final yet, so not counted
+        assert_eq!(e.stats().filtered, 0);
+        assert_eq!(e.next_deadline(), Some(t0 + wait));
+
+        // A removed target is forgotten, the other one comes back when due
+        e.on_event(FsEvent::Removed("/b/.DS_Store".into()), t0);
+        assert!(e.on_tick(t0 + wait - DEBOUNCE).is_empty());
+        assert_eq!(e.on_tick(t0 + wait), vec![handle("/a/.DS_Store")]);
+        assert_eq!(e.next_deadline(), None);
+        e.record(Path::new("/a/.DS_Store"), Outcome::Deleted, t0 + wait);
+        assert_eq!((e.stats().deleted, e.stats().filtered), (1, 0));
+    }
+
+    #[test]
+    fn test_removed_root_drops_retries() {
+        let t0 = Instant::now();
+        let roots = [PathBuf::from("/a"), PathBuf::from("/b")];
+        let mut e = Engine::new(DEBOUNCE).with_roots(&roots, Duration::from_secs(3600), t0);
+        let wait = Duration::from_secs(600);
+
+        e.record(Path::new("/a/x/.DS_Store"), Outcome::TooNew(wait), t0);
+        e.record(Path::new("/b/.DS_Store"), Outcome::TooNew(wait), t0);
+        assert!(e.remove_root(Path::new("/a")));
+        assert_eq!(e.on_tick(t0 + wait), vec![handle("/b/.DS_Store")]);
+    }
+
+    #[test]
+    fn test_retries_are_bounded() {
+        let mut e = Engine::new(DEBOUNCE);
+        let t0 = Instant::now();
+        let wait = Duration::from_secs(600);
+
+        for i in 0..DEFERRED_CAPACITY + 10 {
+            let path = PathBuf::from(format!("/r/{}/.DS_Store", i));
+            e.record(&path, Outcome::TooNew(wait), t0);
+        }
+        assert_eq!(e.deferred.len(), DEFERRED_CAPACITY);
+        // A known path may still move its retry time
+        e.record(Path::new("/r/0/.DS_Store"), Outcome::TooNew(wait * 2), t0);
+        assert_eq!(e.on_tick(t0 + wait).len(), DEFERRED_CAPACITY - 1);
+    }
+}
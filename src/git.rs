@@ -15,6 +15,8 @@ pub fn is_available() -> bool {
 }
 
 /// Check if a file is tracked by git (would affect commit history if deleted)
+///
+/// A directory counts as tracked when any file under it is.
 pub fn is_git_tracked(path: &Path) -> bool {
     // First check if we're in a git repo
     let Some(parent) = path.parent() else { return false };
@@ -31,13 +33,23 @@ pub fn is_git_tracked(path: &Path) -> bool {
 
 /// Result of git safety check
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct SafetyResult {
-    pub safe: Vec<std::path::PathBuf>,      // Files safe to delete
-    pub tracked: Vec<std::path::PathBuf>,   // Files tracked by git
+    /// Files safe to delete
+    pub safe: Vec<std::path::PathBuf>,
+    /// Files tracked by git
+    pub tracked: Vec<std::path::PathBuf>,
 }
 
 /// Check multiple files for git tracking
+///
+/// Files outside any repository are safe. Without git nothing can be shown
+/// to be untracked, so every file counts as tracked.
 pub fn check_files(files: &[std::path::PathBuf]) -> SafetyResult {
+    if !is_available() {
+        return SafetyResult { safe: Vec::new(), tracked: files.to_vec() };
+    }
+
     let mut safe = Vec::new();
     let mut tracked = Vec::new();
 
@@ -49,7 +61,6 @@ pub fn check_files(files: &[std::path::PathBuf]) -> SafetyResult {
         }
     }
 
-
     SafetyResult { safe, tracked }
 }
 
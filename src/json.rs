@@ -0,0 +1,128 @@
+//! Minimal JSON encoding for machine-readable output
+//!
+//! Only what dsk emits is supported: flat objects of strings, numbers,
+//! booleans and pre-encoded nested values. Keeps the binary free of serde.
+
+use std::{
+    fmt::Write,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Escape a string as a quoted JSON string literal
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encode a list of pre-encoded JSON values as an array
+pub fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Current unix time in milliseconds
+pub fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis()
+}
+
+/// Builder for a single JSON object, keys are emitted in insertion order
+#[derive(Default)]
+pub struct Object {
+    buf: String,
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(&mut self, key: &str) {
+        self.buf.push(if self.buf.is_empty() { '{' } else { ',' });
+        self.buf.push_str(&string(key));
+        self.buf.push(':');
+    }
+
+    pub fn str(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        self.buf.push_str(&string(value));
+        self
+    }
+
+    pub fn path(self, key: &str, value: &Path) -> Self {
+        self.str(key, &value.to_string_lossy())
+    }
+
+    pub fn num<N: Into<u128>>(mut self, key: &str, value: N) -> Self {
+        self.key(key);
+        let _ = write!(self.buf, "{}", value.into());
+        self
+    }
+
+    pub fn bool(mut self, key: &str, value: bool) -> Self {
+        self.key(key);
+        self.buf.push_str(if value { "true" } else { "false" });
+        self
+    }
+
+    /// Insert an already encoded JSON value (object or array)
+    pub fn raw(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        self.buf.push_str(value);
+        self
+    }
+
+    pub fn finish(mut self) -> String {
+        if self.buf.is_empty() {
+            self.buf.push('{');
+        }
+        self.buf.push('}');
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_escape() {
+        assert_eq!(string("plain"), r#""plain""#);
+        assert_eq!(string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(string("line\nbreak\u{1}"), r#""line\nbreak\u0001""#);
+    }
+
+    #[test]
+    fn test_object() {
+        assert_eq!(Object::new().finish(), "{}");
+
+        let obj = Object::new()
+            .str("event", "deleted")
+            .num("count", 3u64)
+            .bool("dry_run", false)
+            .raw("files", &array(vec![string("/a/.DS_Store")]))
+            .finish();
+        assert_eq!(
+            obj,
+            r#"{"event":"deleted","count":3,"dry_run":false,"files":["/a/.DS_Store"]}"#
+        );
+    }
+}
@@ -1,169 +1,1152 @@
-//! Core deletion logic
+//! Core deletion logic: scanning for targets and deleting them
 
-use crate::{consts::TARGET_FILE, log};
+use crate::{
+    consts::*,
+    git, json,
+    protect::{Protector, Reason},
+};
 use jwalk::WalkDir;
 use std::{
-    fs,
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{Arc, Mutex, mpsc},
+    thread,
     time::{Duration, Instant},
 };
 
 /// Options controlling kill behavior
-#[derive(Default, Clone)]
+///
+/// ```
+/// use ds_store_killer::KillOptions;
+///
+/// let opts = KillOptions::new().dry_run(true).skip_tracked(true);
+/// assert!(opts.dry_run);
+/// ```
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
 pub struct KillOptions {
+    /// Report targets without deleting them
     pub dry_run: bool,
-    pub quiet: bool,
+    /// Leave git-tracked targets in place
+    pub skip_tracked: bool,
+    /// Rules for targets that must be left alone
+    pub protect: Protector,
+    /// Worker threads for git checks and deletions; 0 or 1 works on the
+    /// calling thread
+    pub jobs: usize,
+    /// Report outcomes in the order targets were found, not as they finish
+    pub ordered: bool,
+}
+
+impl KillOptions {
+    /// Delete for real, with no git check and no protection rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dry_run(mut self, on: bool) -> Self {
+        self.dry_run = on;
+        self
+    }
+
+    /// Check each target with git and skip tracked ones (see [`git`]);
+    /// without git every target counts as tracked
+    pub fn skip_tracked(mut self, on: bool) -> Self {
+        self.skip_tracked = on;
+        self
+    }
+
+    pub fn protect(mut self, protect: Protector) -> Self {
+        self.protect = protect;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    pub fn ordered(mut self, on: bool) -> Self {
+        self.ordered = on;
+        self
+    }
+}
+
+/// A file that could not be deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KillFailure {
+    pub path: PathBuf,
+    pub kind: io::ErrorKind,
 }
 
 /// Result of a kill operation
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct KillResult {
+    /// Targets found, excluding protected ones
     pub found: usize,
+    /// Targets deleted (always 0 in dry-run mode)
     pub deleted: usize,
     pub duration: Duration,
+    /// Files deleted (or matched, in dry-run mode)
+    pub files: Vec<PathBuf>,
+    /// Files whose deletion failed
+    pub failures: Vec<KillFailure>,
+    /// Files left alone by a protection rule (not counted as found)
+    pub protected: Vec<(PathBuf, Reason)>,
+    /// Git-tracked files left in place (not counted as found)
+    pub skipped: Vec<PathBuf>,
+    pub stats: Stats,
+}
+
+/// What a run looked at and where it spent its time
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Directories read while walking (0 for an explicit file list)
+    pub dirs: usize,
+    /// Files and directories the walk saw
+    pub entries: usize,
+    /// Directories not entered because they matched an exclude pattern
+    pub pruned: usize,
+    /// Targets deleted (or matched, in dry-run mode) per type
+    pub by_type: Vec<(Target, usize)>,
+    /// Bytes freed, or that would be freed in dry-run mode
+    pub bytes: u64,
+    /// Targets that failed the age/size [`Filter`]
+    pub filtered: usize,
+    /// Wall time until the walk was over
+    pub walk: Duration,
+    /// Time spent on git checks, summed over workers
+    pub git: Duration,
+    /// Time spent deleting, summed over workers
+    pub delete: Duration,
+    /// Whether the scan cache was hit, if it was consulted at all
+    pub cache_hit: Option<bool>,
+}
+
+impl Stats {
+    /// Directories read per second of walking
+    pub fn dirs_per_sec(&self) -> f64 {
+        per_sec(self.dirs, self.walk)
+    }
+
+    /// `n` targets in words, broken down by [`Stats::by_type`] unless all
+    /// are `.DS_Store` files: `2 .DS_Store file(s)`, `3 target(s) (2
+    /// .DS_Store, 1 __MACOSX)`
+    pub fn describe(&self, n: usize) -> String {
+        if self.by_type.iter().all(|&(t, _)| t == Target::DsStore) {
+            return format!("{} {} file(s)", n, TARGET_FILE);
+        }
+        let parts: Vec<String> =
+            self.by_type.iter().map(|(t, c)| format!("{} {}", c, t.name())).collect();
+        format!("{} target(s) ({})", n, parts.join(", "))
+    }
+
+    fn count(&mut self, target: Target) {
+        match self.by_type.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, n)) => *n += 1,
+            None => self.by_type.push((target, 1)),
+        }
+    }
+
+    /// Add the counters and timings of another phase of the same run
+    pub fn add(&mut self, other: &Stats) {
+        self.dirs += other.dirs;
+        self.entries += other.entries;
+        self.pruned += other.pruned;
+        for &(target, n) in &other.by_type {
+            match self.by_type.iter_mut().find(|(t, _)| *t == target) {
+                Some((_, m)) => *m += n,
+                None => self.by_type.push((target, n)),
+            }
+        }
+        self.bytes += other.bytes;
+        self.filtered += other.filtered;
+        self.walk += other.walk;
+        self.git += other.git;
+        self.delete += other.delete;
+        self.cache_hit = other.cache_hit.or(self.cache_hit);
+    }
+}
+
+/// Items per second, 0 for an empty interval
+pub fn per_sec(n: usize, d: Duration) -> f64 {
+    if d.is_zero() { 0.0 } else { n as f64 / d.as_secs_f64() }
+}
+
+impl KillResult {
+    /// Count failures per error kind, most frequent first
+    pub fn failures_by_kind(&self) -> Vec<(io::ErrorKind, usize)> {
+        let mut counts: Vec<(io::ErrorKind, usize)> = Vec::new();
+        for f in &self.failures {
+            match counts.iter_mut().find(|(k, _)| *k == f.kind) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((f.kind, 1)),
+            }
+        }
+        counts.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        counts
+    }
+
+    /// Process exit code for this result
+    ///
+    /// Failures take precedence over git skips; in dry-run mode any match
+    /// (including skipped ones) counts as found.
+    pub fn exit_code(&self, dry_run: bool) -> i32 {
+        let skipped = self.skipped.len();
+        if dry_run {
+            if self.found + skipped > 0 { EXIT_FOUND } else { EXIT_OK }
+        } else if !self.failures.is_empty() {
+            EXIT_PARTIAL
+        } else if skipped > 0 {
+            EXIT_GIT_SKIPPED
+        } else {
+            EXIT_OK
+        }
+    }
+
+    /// Encode as the `kill --format json` summary object
+    pub fn to_json(&self, dry_run: bool) -> String {
+        self.summary(dry_run).finish()
+    }
+
+    /// The `--stats` breakdown as a JSON object
+    pub fn stats_json(&self) -> String {
+        let s = &self.stats;
+        let by_type = s
+            .by_type
+            .iter()
+            .fold(json::Object::new(), |obj, (t, n)| obj.num(t.name(), *n as u64));
+        let cache = match s.cache_hit {
+            Some(hit) => hit.to_string(),
+            None => "null".into(),
+        };
+        json::Object::new()
+            .num("dirs", s.dirs as u64)
+            .num("entries", s.entries as u64)
+            .num("pruned", s.pruned as u64)
+            .raw("found_by_type", &by_type.finish())
+            .num("bytes", s.bytes)
+            .num("skipped_git", self.skipped.len() as u64)
+            .num("skipped_protected", self.protected.len() as u64)
+            .num("skipped_filter", s.filtered as u64)
+            .num("errors", self.failures.len() as u64)
+            .num("walk_ms", s.walk.as_millis())
+            .num("git_ms", s.git.as_millis())
+            .num("delete_ms", s.delete.as_millis())
+            .num("dirs_per_sec", s.dirs_per_sec().round() as u64)
+            .num("files_per_sec", per_sec(self.found, self.duration).round() as u64)
+            .raw("cache_hit", &cache)
+            .finish()
+    }
+
+    /// The summary object, left open for more keys
+    pub(crate) fn summary(&self, dry_run: bool) -> json::Object {
+        let skipped = &self.skipped;
+        let status = if dry_run { "dry-run" } else { "deleted" };
+        let entries = self
+            .files
+            .iter()
+            .map(|p| json::Object::new().path("path", p).str("status", status).finish())
+            .chain(skipped.iter().map(|p| {
+                json::Object::new().path("path", p).str("status", "skipped-git").finish()
+            }))
+            .chain(self.failures.iter().map(|f| {
+                json::Object::new()
+                    .path("path", &f.path)
+                    .str("status", "error")
+                    .str("error", &f.kind.to_string())
+                    .finish()
+            }))
+            .chain(self.protected.iter().map(|(p, reason)| {
+                json::Object::new()
+                    .path("path", p)
+                    .str("status", "protected")
+                    .str("reason", &reason.to_string())
+                    .finish()
+            }));
+
+        let by_kind = self
+            .failures_by_kind()
+            .into_iter()
+            .fold(json::Object::new(), |obj, (kind, n)| obj.num(&kind.to_string(), n as u64));
+
+        json::Object::new()
+            .num("found", (self.found + skipped.len()) as u64)
+            .num("deleted", self.deleted as u64)
+            .num("skipped", skipped.len() as u64)
+            .num("protected", self.protected.len() as u64)
+            .num("errors", self.failures.len() as u64)
+            .raw("errors_by_kind", &by_kind.finish())
+            .bool("dry_run", dry_run)
+            .num("duration_ms", self.duration.as_millis())
+            .raw("files", &json::array(entries))
+    }
 }
 
 impl std::fmt::Display for KillResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.deleted > 0 {
-            write!(f, "Deleted {} .DS_Store file(s)", self.deleted)
+            write!(f, "Deleted {}", self.stats.describe(self.deleted))?;
         } else if self.found > 0 {
-            write!(f, "Found {} .DS_Store file(s)", self.found)
+            write!(f, "Found {}", self.stats.describe(self.found))?;
+        } else if self.protected.is_empty() {
+            return write!(f, "No .DS_Store files found");
         } else {
-            write!(f, "No .DS_Store files found")
+            write!(f, "No .DS_Store files to delete")?;
+        }
+        if !self.failures.is_empty() {
+            write!(f, ", {} failed", self.failures.len())?;
+        }
+        if !self.protected.is_empty() {
+            write!(f, ", {} protected", self.protected.len())?;
         }
+        Ok(())
     }
 }
 
-/// Check if a path is the target file
-#[inline]
-pub fn is_target(path: &Path) -> bool {
-    path.file_name().map_or(false, |n| n == TARGET_FILE)
+/// Why a target was left in place
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skipped {
+    /// Failed the age/size [`Filter`]
+    Filtered(Skip),
+    /// Matched a protection rule
+    Protected(Reason),
+    /// Tracked by git
+    GitTracked,
+    /// A `__MACOSX` folder holding this file, which is not AppleDouble
+    Foreign(PathBuf),
 }
 
-/// Check if a path matches any exclude pattern
-#[inline]
-pub fn is_excluded(path: &Path, excludes: &[String]) -> bool {
-    let s = path.to_string_lossy();
-    excludes.iter().any(|p| s.contains(p))
+/// Receives each step of a scan or kill as it happens
+///
+/// Every method has an empty default, so an observer implements only what
+/// it needs. Pair observers with a tuple: `(printer, notifier)`.
+pub trait KillObserver {
+    /// A target passed the filter
+    fn on_found(&mut self, _path: &Path) {}
+    /// A target was left in place
+    fn on_skipped(&mut self, _path: &Path, _why: &Skipped) {}
+    /// A target was deleted, or would have been in dry-run mode
+    fn on_deleted(&mut self, _path: &Path, _dry_run: bool) {}
+    /// Deleting a target failed
+    fn on_error(&mut self, _path: &Path, _error: &io::Error) {}
+    /// The run is over
+    fn on_finished(&mut self, _result: &KillResult) {}
 }
 
-/// Streaming scan - finds files and calls callback for each one immediately
-pub fn scan_streaming<F>(dir: &Path, recursive: bool, excludes: &[String], mut callback: F) -> usize
-where
-    F: FnMut(&Path),
-{
-    let mut count = 0;
+/// Observes nothing
+impl KillObserver for () {}
 
-    if recursive {
-        // CRITICAL: skip_hidden(false) to include .DS_Store files!
-        for entry in WalkDir::new(dir)
-            .skip_hidden(false)
-            .into_iter()
-            .filter_map(Result::ok)
-        {
-            let path = entry.path();
-            if is_target(&path) && !is_excluded(&path, excludes) {
-                callback(&path);
-                count += 1;
-            }
+impl<T: KillObserver + ?Sized> KillObserver for &mut T {
+    fn on_found(&mut self, path: &Path) {
+        (**self).on_found(path)
+    }
+    fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+        (**self).on_skipped(path, why)
+    }
+    fn on_deleted(&mut self, path: &Path, dry_run: bool) {
+        (**self).on_deleted(path, dry_run)
+    }
+    fn on_error(&mut self, path: &Path, error: &io::Error) {
+        (**self).on_error(path, error)
+    }
+    fn on_finished(&mut self, result: &KillResult) {
+        (**self).on_finished(result)
+    }
+}
+
+/// An optional observer, e.g. one enabled by a flag
+impl<T: KillObserver> KillObserver for Option<T> {
+    fn on_found(&mut self, path: &Path) {
+        if let Some(o) = self {
+            o.on_found(path)
+        }
+    }
+    fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+        if let Some(o) = self {
+            o.on_skipped(path, why)
+        }
+    }
+    fn on_deleted(&mut self, path: &Path, dry_run: bool) {
+        if let Some(o) = self {
+            o.on_deleted(path, dry_run)
+        }
+    }
+    fn on_error(&mut self, path: &Path, error: &io::Error) {
+        if let Some(o) = self {
+            o.on_error(path, error)
+        }
+    }
+    fn on_finished(&mut self, result: &KillResult) {
+        if let Some(o) = self {
+            o.on_finished(result)
+        }
+    }
+}
+
+/// Both observers, first then second
+impl<A: KillObserver, B: KillObserver> KillObserver for (A, B) {
+    fn on_found(&mut self, path: &Path) {
+        self.0.on_found(path);
+        self.1.on_found(path);
+    }
+    fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+        self.0.on_skipped(path, why);
+        self.1.on_skipped(path, why);
+    }
+    fn on_deleted(&mut self, path: &Path, dry_run: bool) {
+        self.0.on_deleted(path, dry_run);
+        self.1.on_deleted(path, dry_run);
+    }
+    fn on_error(&mut self, path: &Path, error: &io::Error) {
+        self.0.on_error(path, error);
+        self.1.on_error(path, error);
+    }
+    fn on_finished(&mut self, result: &KillResult) {
+        self.0.on_finished(result);
+        self.1.on_finished(result);
+    }
+}
+
+/// What happened to one target
+enum Outcome {
+    Protected(Reason),
+    Tracked,
+    Foreign(PathBuf),
+    /// Deleted, or would have been in dry-run mode, freeing this many bytes
+    Deleted(u64),
+    /// Already gone (e.g. removed concurrently) - nothing to report
+    Gone,
+    Failed(io::Error),
+}
+
+/// Time one target took, by phase
+#[derive(Default)]
+struct Spent {
+    git: Duration,
+    delete: Duration,
+}
+
+/// Delete a single target unless dry-run, protected or tracked; `has_git`
+/// tells whether git could be run at all
+fn process(path: &Path, opts: &KillOptions, has_git: bool, spent: &mut Spent) -> Outcome {
+    let start = Instant::now();
+    if let Some(reason) = opts.protect.check(path) {
+        return Outcome::Protected(reason);
+    }
+    if opts.skip_tracked {
+        // Without git nothing can be shown to be untracked
+        if !has_git {
+            return Outcome::Tracked;
+        }
+        let t = Instant::now();
+        let tracked = git::is_git_tracked(path);
+        spent.git = t.elapsed();
+        if tracked {
+            return Outcome::Tracked;
+        }
+    }
+    let folder = is_macosx_dir(path);
+    let size = if folder {
+        match macosx_size(path) {
+            Ok(size) => size,
+            Err(file) => return Outcome::Foreign(file),
         }
     } else {
-        let target = dir.join(TARGET_FILE);
-        if target.exists() && !is_excluded(&target, excludes) {
-            callback(&target);
-            count += 1;
+        fs::symlink_metadata(path).map_or(0, |m| m.len())
+    };
+    let outcome = if opts.dry_run {
+        Outcome::Deleted(size)
+    } else {
+        let removed = if folder { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        match removed {
+            Ok(()) => Outcome::Deleted(size),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Outcome::Gone,
+            Err(e) => Outcome::Failed(e),
         }
+    };
+    spent.delete = start.elapsed().saturating_sub(spent.git);
+    outcome
+}
+
+/// Accumulates per-file outcomes while killing
+#[derive(Default)]
+struct Tally {
+    found: usize,
+    files: Vec<PathBuf>,
+    failures: Vec<KillFailure>,
+    protected: Vec<(PathBuf, Reason)>,
+    skipped: Vec<PathBuf>,
+    stats: Stats,
+}
+
+impl Tally {
+    fn record(&mut self, path: PathBuf, outcome: Outcome, dry_run: bool, obs: &mut impl KillObserver) {
+        match outcome {
+            Outcome::Protected(reason) => {
+                obs.on_skipped(&path, &Skipped::Protected(reason.clone()));
+                self.protected.push((path, reason));
+            }
+            Outcome::Tracked => {
+                obs.on_skipped(&path, &Skipped::GitTracked);
+                self.skipped.push(path);
+            }
+            Outcome::Foreign(file) => obs.on_skipped(&path, &Skipped::Foreign(file)),
+            Outcome::Deleted(size) => {
+                self.found += 1;
+                self.stats.bytes += size;
+                let name = path.file_name().and_then(|n| n.to_str());
+                if let Some(target) = name.and_then(Target::of) {
+                    self.stats.count(target);
+                }
+                obs.on_deleted(&path, dry_run);
+                self.files.push(path);
+            }
+            Outcome::Gone => self.found += 1,
+            Outcome::Failed(e) => {
+                self.found += 1;
+                obs.on_error(&path, &e);
+                self.failures.push(KillFailure { path, kind: e.kind() });
+            }
+        }
+    }
+
+    fn spend(&mut self, spent: Spent) {
+        self.stats.git += spent.git;
+        self.stats.delete += spent.delete;
+    }
+
+    fn finish(self, opts: &KillOptions, start: Instant, obs: &mut impl KillObserver) -> KillResult {
+        let result = KillResult {
+            found: self.found,
+            deleted: if opts.dry_run { 0 } else { self.files.len() },
+            duration: start.elapsed(),
+            files: self.files,
+            failures: self.failures,
+            protected: self.protected,
+            skipped: self.skipped,
+            stats: self.stats,
+        };
+        obs.on_finished(&result);
+        result
     }
+}
+
+/// A finished job: submission index, target, outcome and time taken
+type Done = (usize, PathBuf, Outcome, Spent);
 
-    count
+/// Outcomes from the workers, passed to the observer in submission order
+/// when `ordered` is set and as they complete otherwise
+struct Collector {
+    ordered: bool,
+    next: usize,
+    pending: BTreeMap<usize, (PathBuf, Outcome)>,
 }
 
-/// Streaming kill - find and delete files as they're discovered
-pub fn kill_streaming(
-    dir: &Path,
-    recursive: bool,
-    excludes: &[String],
+impl Collector {
+    fn accept(
+        &mut self,
+        (i, path, outcome, spent): Done,
+        tally: &mut Tally,
+        dry_run: bool,
+        obs: &mut impl KillObserver,
+    ) {
+        tally.spend(spent);
+        if !self.ordered {
+            return tally.record(path, outcome, dry_run, obs);
+        }
+        self.pending.insert(i, (path, outcome));
+        while let Some((path, outcome)) = self.pending.remove(&self.next) {
+            tally.record(path, outcome, dry_run, obs);
+            self.next += 1;
+        }
+    }
+}
+
+/// Kill every target `feed` submits, on `opts.jobs` worker threads
+///
+/// `feed` returns what its walk saw. Observers are always called on this
+/// thread.
+fn run(
     opts: &KillOptions,
+    obs: &mut impl KillObserver,
+    feed: impl FnOnce(&mut dyn FnMut(PathBuf, Result<(), Skip>)) -> Stats,
 ) -> KillResult {
     let start = Instant::now();
-    let found = AtomicUsize::new(0);
-    let deleted = AtomicUsize::new(0);
+    let mut tally = Tally::default();
+    let has_git = opts.skip_tracked && git::is_available();
 
-    if recursive {
-        for entry in WalkDir::new(dir)
-            .skip_hidden(false)
-            .into_iter()
-            .filter_map(Result::ok)
-        {
-            let path = entry.path();
-            if is_target(&path) && !is_excluded(&path, excludes) {
-                found.fetch_add(1, Ordering::Relaxed);
-
-                if !opts.quiet {
-                    if opts.dry_run {
-                        log::dry(&path);
-                    } else {
-                        log::kill(&path);
+    if opts.jobs <= 1 {
+        let walked = feed(&mut |path, check| match check {
+            Ok(()) => {
+                obs.on_found(&path);
+                let mut spent = Spent::default();
+                let outcome = process(&path, opts, has_git, &mut spent);
+                tally.spend(spent);
+                tally.record(path, outcome, opts.dry_run, obs);
+            }
+            Err(skip) => {
+                tally.stats.filtered += 1;
+                obs.on_skipped(&path, &Skipped::Filtered(skip));
+            }
+        });
+        // An explicit file list has no walk to time
+        if walked.dirs > 0 {
+            let busy = tally.stats.git + tally.stats.delete;
+            tally.stats.walk = start.elapsed().saturating_sub(busy);
+        }
+        tally.stats.add(&walked);
+        return tally.finish(opts, start, obs);
+    }
+
+    // Bounded, so a fast walk doesn't queue up the whole tree
+    let (job_tx, job_rx) = mpsc::sync_channel::<(usize, PathBuf)>(opts.jobs * 2);
+    let job_rx = Mutex::new(job_rx);
+    let (done_tx, done_rx) = mpsc::channel::<Done>();
+    thread::scope(|scope| {
+        for _ in 0..opts.jobs {
+            let (job_rx, done_tx) = (&job_rx, done_tx.clone());
+            scope.spawn(move || {
+                loop {
+                    let job = job_rx.lock().map_err(drop).and_then(|rx| rx.recv().map_err(drop));
+                    let Ok((i, path)) = job else { break };
+                    let mut spent = Spent::default();
+                    let outcome = process(&path, opts, has_git, &mut spent);
+                    if done_tx.send((i, path, outcome, spent)).is_err() {
+                        break;
                     }
                 }
+            });
+        }
+        drop(done_tx);
 
-                if !opts.dry_run && fs::remove_file(&path).is_ok() {
-                    deleted.fetch_add(1, Ordering::Relaxed);
+        let mut collector = Collector { ordered: opts.ordered, next: 0, pending: BTreeMap::new() };
+        let mut sent = 0;
+        // Time spent waiting for a free worker is deletion, not walking
+        let mut blocked = Duration::ZERO;
+        let walked = feed(&mut |path, check| match check {
+            Ok(()) => {
+                obs.on_found(&path);
+                let waiting = Instant::now();
+                let _ = job_tx.send((sent, path));
+                blocked += waiting.elapsed();
+                sent += 1;
+                for done in done_rx.try_iter() {
+                    collector.accept(done, &mut tally, opts.dry_run, obs);
                 }
             }
-        }
-    } else {
-        let target = dir.join(TARGET_FILE);
-        if target.exists() && !is_excluded(&target, excludes) {
-            found.fetch_add(1, Ordering::Relaxed);
-
-            if !opts.quiet {
-                if opts.dry_run {
-                    log::dry(&target);
-                } else {
-                    log::kill(&target);
-                }
+            Err(skip) => {
+                tally.stats.filtered += 1;
+                obs.on_skipped(&path, &Skipped::Filtered(skip));
             }
+        });
+        if walked.dirs > 0 {
+            tally.stats.walk = start.elapsed().saturating_sub(blocked);
+        }
+        tally.stats.add(&walked);
 
-            if !opts.dry_run && fs::remove_file(&target).is_ok() {
-                deleted.fetch_add(1, Ordering::Relaxed);
-            }
+        drop(job_tx);
+        for done in done_rx {
+            collector.accept(done, &mut tally, opts.dry_run, obs);
         }
+    });
+    tally.finish(opts, start, obs)
+}
+
+/// Metadata conditions a target must meet to be deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Filter {
+    /// Only targets last modified at least this long ago
+    pub older_than: Option<Duration>,
+    /// Only targets last modified at most this long ago
+    pub newer_than: Option<Duration>,
+    pub min_size: Option<u64>,
+    /// Larger targets are left in place and flagged
+    pub max_size: Option<u64>,
+}
+
+/// Why a target did not pass the [`Filter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    New,
+    Old,
+    Small,
+    Large(u64),
+    /// Metadata couldn't be read, so the filter can't be applied
+    Unreadable,
+}
+
+impl std::fmt::Display for Skip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::New => write!(f, "modified too recently"),
+            Self::Old => write!(f, "not modified recently"),
+            Self::Small => write!(f, "smaller than --min-size"),
+            Self::Large(n) => write!(f, "unusually large ({} bytes)", n),
+            Self::Unreadable => write!(f, "metadata unreadable"),
+        }
+    }
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
     }
 
-    KillResult {
-        found: found.load(Ordering::Relaxed),
-        deleted: if opts.dry_run { 0 } else { deleted.load(Ordering::Relaxed) },
-        duration: start.elapsed(),
+    /// Check a target on disk; metadata is only read when a filter is set
+    ///
+    /// A folder target (`__MACOSX`) is as old as the folder itself and as
+    /// large as the files in it.
+    pub fn check(&self, path: &Path) -> Result<(), Skip> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            // Already gone: let the deletion itself report it
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(_) => return Err(Skip::Unreadable),
+        };
+        let age = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .unwrap_or(Duration::ZERO);
+        let size = if meta.is_dir() { tree_size(path) } else { meta.len() };
+        self.check_meta(size, age)
+    }
+
+    /// How long until `path` is old enough for `older_than`, if it has a
+    /// readable modification time
+    pub fn wait(&self, path: &Path) -> Option<Duration> {
+        let older_than = self.older_than?;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        let age = modified.elapsed().unwrap_or(Duration::ZERO);
+        Some(older_than.saturating_sub(age))
+    }
+
+    fn check_meta(&self, size: u64, age: Duration) -> Result<(), Skip> {
+        if self.max_size.is_some_and(|max| size > max) {
+            return Err(Skip::Large(size));
+        }
+        if self.min_size.is_some_and(|min| size < min) {
+            return Err(Skip::Small);
+        }
+        if self.older_than.is_some_and(|d| age < d) {
+            return Err(Skip::New);
+        }
+        if self.newer_than.is_some_and(|d| age > d) {
+            return Err(Skip::Old);
+        }
+        Ok(())
     }
 }
 
-/// Kill a specific list of files
-pub fn kill_files(files: &[PathBuf], opts: &KillOptions) -> KillResult {
-    let start = Instant::now();
-    let found = files.len();
-    let mut deleted = 0;
-
-    for path in files {
-        if !opts.quiet {
-            if opts.dry_run {
-                log::dry(path);
-            } else {
-                log::kill(path);
+/// How far and across which boundaries a recursive walk goes
+///
+/// Directories in [`PSEUDO_FS`] (`/proc`, `/dev`, ...) are never entered.
+/// Depths count like `find`: the root's own `.DS_Store` is at depth 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Traversal {
+    /// Shallowest target to report
+    pub min_depth: Option<usize>,
+    /// Deepest target to report; deeper directories are not read at all
+    pub max_depth: Option<usize>,
+    /// Stay on the root's file system (compares device ids)
+    pub one_file_system: bool,
+    /// Descend into symlinked directories; each directory is read at most
+    /// once, which also breaks symlink loops
+    pub follow_symlinks: bool,
+}
+
+impl Traversal {
+    /// The same limits for a walk starting `depth` levels below the root
+    pub fn below(&self, depth: usize) -> Self {
+        Self {
+            min_depth: self.min_depth.map(|d| d.saturating_sub(depth)),
+            max_depth: self.max_depth.map(|d| d.saturating_sub(depth)),
+            ..self.clone()
+        }
+    }
+
+    /// Whether a target at `depth` is within the limits
+    pub fn admits(&self, depth: usize) -> bool {
+        self.min_depth.is_none_or(|min| depth >= min)
+            && self.max_depth.is_none_or(|max| depth <= max)
+    }
+
+    fn is_limited(&self) -> bool {
+        self.min_depth.is_some() || self.max_depth.is_some()
+    }
+}
+
+/// Parse a size such as `512`, `4k`, `1.5M` or `2GB` (1024-based)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches(['b', 'i']);
+    let (num, mult) = match digits.char_indices().last() {
+        Some((i, 'k')) => (&digits[..i], 1u64 << 10),
+        Some((i, 'm')) => (&digits[..i], 1 << 20),
+        Some((i, 'g')) => (&digits[..i], 1 << 30),
+        _ => (digits, 1),
+    };
+    let n: f64 = num.parse().map_err(|_| format!("invalid size: {}", s))?;
+    if n < 0.0 || !n.is_finite() {
+        return Err(format!("invalid size: {}", s));
+    }
+    Ok((n * mult as f64) as u64)
+}
+
+/// Kinds of macOS metadata that dsk removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Finder view settings (`.DS_Store`)
+    DsStore,
+    /// AppleDouble resource fork / attribute file (`._name`)
+    AppleDouble,
+    /// Resource fork folder made by Archive Utility (`__MACOSX`)
+    Macosx,
+}
+
+impl Target {
+    /// Which target a file or directory name is, if any
+    pub fn of(name: &str) -> Option<Self> {
+        match name {
+            TARGET_FILE => Some(Self::DsStore),
+            MACOSX_DIR => Some(Self::Macosx),
+            n if n.len() > APPLE_DOUBLE_PREFIX.len() && n.starts_with(APPLE_DOUBLE_PREFIX) => {
+                Some(Self::AppleDouble)
             }
+            _ => None,
+        }
+    }
+
+    /// How the target appears on disk, e.g. in `--stats`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DsStore => TARGET_FILE,
+            Self::AppleDouble => "._*",
+            Self::Macosx => MACOSX_DIR,
+        }
+    }
+}
+
+/// Check if a path is the target file
+///
+/// ```
+/// use std::path::Path;
+/// assert!(ds_store_killer::is_target(Path::new("/a/.DS_Store")));
+/// ```
+#[inline]
+pub fn is_target(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).and_then(Target::of) == Some(Target::DsStore)
+}
+
+/// Check if a path is a `__MACOSX` folder (symlinks are not followed)
+pub fn is_macosx_dir(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).and_then(Target::of) == Some(Target::Macosx)
+        && fs::symlink_metadata(path).is_ok_and(|m| m.is_dir())
+}
+
+/// Total size of a `__MACOSX` folder holding only AppleDouble files, or
+/// the first entry that is something else
+///
+/// Subdirectories are fine; symlinks, other files and unreadable entries
+/// are not, so only pure resource-fork folders get removed.
+fn macosx_size(dir: &Path) -> Result<u64, PathBuf> {
+    let mut size = 0;
+    for entry in WalkDir::new(dir).skip_hidden(false).min_depth(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(e.path().map_or_else(|| dir.to_path_buf(), Path::to_path_buf)),
+        };
+        if entry.file_type().is_dir() {
+            continue;
         }
+        let name = entry.file_name().to_str().and_then(Target::of);
+        if !entry.file_type().is_file() || name != Some(Target::AppleDouble) {
+            return Err(entry.path());
+        }
+        size += entry.metadata().map_or(0, |m| m.len());
+    }
+    Ok(size)
+}
+
+/// Total size of the files under `dir`
+fn tree_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .skip_hidden(false)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.metadata().map_or(0, |m| m.len()))
+        .sum()
+}
+
+/// Check if a path matches any exclude pattern
+///
+/// Patterns match anywhere in the path as plain substrings.
+///
+/// ```
+/// use std::path::Path;
+/// let ex = ["node_modules".to_string()];
+/// assert!(ds_store_killer::is_excluded(Path::new("/a/node_modules/.DS_Store"), &ex));
+/// ```
+#[inline]
+pub fn is_excluded(path: &Path, excludes: &[String]) -> bool {
+    let s = path.to_string_lossy();
+    excludes.iter().any(|p| s.contains(p))
+}
 
-        if !opts.dry_run && fs::remove_file(path).is_ok() {
-            deleted += 1;
+/// Finds targets under one directory
+///
+/// ```no_run
+/// use ds_store_killer::{KillOptions, Scanner};
+///
+/// let result = Scanner::new("/Users/me/Projects")
+///     .recursive(true)
+///     .exclude("node_modules")
+///     .kill(&KillOptions::new().dry_run(true));
+/// println!("{}", result);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    root: PathBuf,
+    recursive: bool,
+    excludes: Vec<String>,
+    filter: Filter,
+    traversal: Traversal,
+    macosx: bool,
+}
+
+impl Scanner {
+    /// Scan only `root` itself until [`recursive`](Self::recursive) is set
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            recursive: false,
+            excludes: Vec::new(),
+            filter: Filter::default(),
+            traversal: Traversal::default(),
+            macosx: false,
         }
     }
 
-    KillResult {
-        found,
-        deleted: if opts.dry_run { 0 } else { deleted },
-        duration: start.elapsed(),
+    /// Descend into subdirectories
+    pub fn recursive(mut self, on: bool) -> Self {
+        self.recursive = on;
+        self
+    }
+
+    /// Skip targets whose path contains `pattern`
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.excludes.push(pattern.into());
+        self
+    }
+
+    /// Add several exclude patterns
+    pub fn excludes<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.excludes.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only report targets meeting `filter`; oversized ones are flagged
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Limit which directories a recursive walk enters; a depth limit
+    /// makes the walk recursive
+    pub fn traversal(mut self, traversal: Traversal) -> Self {
+        self.traversal = traversal;
+        self
+    }
+
+    /// Also report `__MACOSX` folders, which are removed with their content
+    ///
+    /// A folder holding anything but AppleDouble files is left in place
+    /// when killing and reported as [`Skipped::Foreign`].
+    pub fn macosx(mut self, on: bool) -> Self {
+        self.macosx = on;
+        self
+    }
+
+    /// Call `f` for each target as soon as it is found; returns the count
+    pub fn for_each<F: FnMut(&Path)>(&self, mut f: F) -> usize {
+        let mut count = 0;
+        self.walk(|path, check| {
+            if check.is_ok() {
+                f(&path);
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Collect every target
+    pub fn scan(&self) -> Vec<PathBuf> {
+        self.scan_with(&mut ())
+    }
+
+    /// Collect every target, reporting finds and filtered targets to `obs`
+    pub fn scan_with(&self, obs: &mut impl KillObserver) -> Vec<PathBuf> {
+        self.scan_stats(obs).0
+    }
+
+    /// Like [`scan_with`](Self::scan_with), also returning what the walk saw
+    pub fn scan_stats(&self, obs: &mut impl KillObserver) -> (Vec<PathBuf>, Stats) {
+        let start = Instant::now();
+        let mut files = Vec::new();
+        let mut filtered = 0;
+        let mut stats = self.walk(|path, check| match check {
+            Ok(()) => {
+                obs.on_found(&path);
+                files.push(path);
+            }
+            Err(skip) => {
+                filtered += 1;
+                obs.on_skipped(&path, &Skipped::Filtered(skip));
+            }
+        });
+        stats.filtered = filtered;
+        stats.walk = start.elapsed();
+        (files, stats)
+    }
+
+    /// Delete targets as they are found
+    pub fn kill(&self, opts: &KillOptions) -> KillResult {
+        self.kill_with(opts, &mut ())
+    }
+
+    /// Delete targets as they are found, reporting each step to `obs`
+    pub fn kill_with(&self, opts: &KillOptions, obs: &mut impl KillObserver) -> KillResult {
+        run(opts, obs, |submit| self.walk(submit))
     }
+
+    /// Every non-excluded target with the outcome of the filter; returns
+    /// what the walk saw
+    fn walk(&self, mut f: impl FnMut(PathBuf, Result<(), Skip>)) -> Stats {
+        let mut stats = Stats::default();
+        if !self.recursive && !self.traversal.is_limited() {
+            stats.dirs = 1;
+            let target = self.root.join(TARGET_FILE);
+            if target.exists() && !is_excluded(&target, &self.excludes) {
+                let check = self.filter.check(&target);
+                f(target, check);
+            }
+            let folder = self.root.join(MACOSX_DIR);
+            if self.macosx && is_macosx_dir(&folder) && !is_excluded(&folder, &self.excludes) {
+                let check = self.filter.check(&folder);
+                f(folder, check);
+            }
+            return stats;
+        }
+
+        let (macosx, max_depth) = (self.macosx, self.traversal.max_depth);
+        let excludes = self.excludes.clone();
+        let root = fs::metadata(&self.root).ok();
+        let root_dev = root.as_ref().filter(|_| self.traversal.one_file_system).map(|m| m.dev());
+        let root_id = root.map(|m| (m.dev(), m.ino()));
+        let visited = self.traversal.follow_symlinks.then(|| Arc::new(Mutex::new(HashSet::new())));
+        // CRITICAL: skip_hidden(false) to include .DS_Store files!
+        let walk = WalkDir::new(&self.root)
+            .skip_hidden(false)
+            .follow_links(self.traversal.follow_symlinks)
+            .process_read_dir(move |depth, _, _, children| {
+                // The root itself comes through first, without a depth
+                if depth.is_none() {
+                    if let (Some(v), Some(id)) = (&visited, root_id) {
+                        v.lock().map(|mut v| v.insert(id)).ok();
+                    }
+                    return;
+                }
+                for child in children.iter_mut().flatten() {
+                    if !child.file_type.is_dir() {
+                        continue;
+                    }
+                    let path = child.path();
+                    // Contents past max_depth can't match, everything below an
+                    // excluded directory is excluded too, and a __MACOSX folder is
+                    // a target as a whole: don't descend
+                    let mut skip = max_depth.is_some_and(|max| child.depth >= max)
+                        || is_excluded(&path, &excludes)
+                        || (macosx && child.file_name == MACOSX_DIR)
+                        || PSEUDO_FS.iter().any(|p| path == Path::new(p));
+                    if !skip && (root_dev.is_some() || visited.is_some()) {
+                        skip = match child.metadata() {
+                            Ok(m) => {
+                                root_dev.is_some_and(|dev| m.dev() != dev)
+                                    || visited.as_ref().is_some_and(|v| {
+                                        !v.lock().is_ok_and(|mut v| v.insert((m.dev(), m.ino())))
+                                    })
+                            }
+                            Err(_) => true,
+                        };
+                    }
+                    if skip {
+                        child.read_children_path = None;
+                    }
+                }
+            });
+        for entry in walk.into_iter().filter_map(Result::ok) {
+            if entry.read_children_path.is_some() {
+                stats.dirs += 1;
+            }
+            if entry.depth == 0 {
+                continue;
+            }
+            stats.entries += 1;
+            let path = entry.path();
+            let is_dir = entry.file_type.is_dir();
+            if is_excluded(&path, &self.excludes) {
+                if is_dir {
+                    stats.pruned += 1;
+                }
+                continue;
+            }
+            if !self.traversal.admits(entry.depth) {
+                continue;
+            }
+            let folder =
+                macosx && is_dir && entry.file_name == MACOSX_DIR && !entry.path_is_symlink();
+            if is_target(&path) || folder {
+                let check = self.filter.check(&path);
+                f(path, check);
+            }
+        }
+        stats
+    }
+}
+
+/// Kill a specific list of files
+pub fn kill_files(files: &[PathBuf], opts: &KillOptions) -> KillResult {
+    kill_files_with(files, opts, &mut ())
+}
+
+/// Kill a specific list of files, reporting each step to `obs`
+pub fn kill_files_with(
+    files: &[PathBuf],
+    opts: &KillOptions,
+    obs: &mut impl KillObserver,
+) -> KillResult {
+    run(opts, obs, |submit| {
+        for path in files {
+            submit(path.clone(), Ok(()));
+        }
+        Stats::default()
+    })
 }
 
 #[cfg(test)]
@@ -178,6 +1161,16 @@ mod tests {
         assert!(!is_target(Path::new(".DS_Store.bak")));
     }
 
+    #[test]
+    fn test_target_of() {
+        assert_eq!(Target::of(".DS_Store"), Some(Target::DsStore));
+        assert_eq!(Target::of("._photo.jpg"), Some(Target::AppleDouble));
+        assert_eq!(Target::of("__MACOSX"), Some(Target::Macosx));
+        assert_eq!(Target::of("._"), None);
+        assert_eq!(Target::of("_MACOSX"), None);
+        assert_eq!(Target::of("photo.jpg"), None);
+    }
+
     #[test]
     fn test_is_excluded() {
         let ex = vec!["node_modules".into(), ".git".into()];
@@ -186,16 +1179,175 @@ mod tests {
         assert!(!is_excluded(Path::new("/a/src/.DS_Store"), &ex));
     }
 
+    #[test]
+    fn test_filter() {
+        let day = Duration::from_secs(86400);
+        let f = Filter {
+            older_than: Some(day),
+            max_size: Some(64 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(f.check_meta(6148, day * 2), Ok(()));
+        assert_eq!(f.check_meta(6148, Duration::from_secs(60)), Err(Skip::New));
+        assert_eq!(f.check_meta(1 << 20, day * 2), Err(Skip::Large(1 << 20)));
+
+        let f = Filter { newer_than: Some(day), min_size: Some(100), ..Default::default() };
+        assert_eq!(f.check_meta(6148, day * 2), Err(Skip::Old));
+        assert_eq!(f.check_meta(10, Duration::ZERO), Err(Skip::Small));
+        assert!(Filter::default().is_empty());
+
+        // A set filter can't vouch for a file it can't stat
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "x").unwrap();
+        assert_eq!(f.check(&file.join(TARGET_FILE)), Err(Skip::Unreadable));
+        assert_eq!(f.check(&dir.path().join("missing")), Ok(()));
+
+        let f = Filter { older_than: Some(day), ..Default::default() };
+        assert!(f.wait(&file).is_some_and(|w| w > day - Duration::from_secs(60)));
+        assert_eq!(Filter::default().wait(&file), None);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4k"), Ok(4096));
+        assert_eq!(parse_size("4KiB"), Ok(4096));
+        assert_eq!(parse_size("1.5M"), Ok(1536 * 1024));
+        assert_eq!(parse_size("2GB"), Ok(2 << 30));
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("-1k").is_err());
+    }
+
     #[test]
     fn test_kill_result_display() {
-        let r = KillResult { found: 0, deleted: 0, duration: Duration::ZERO };
-        assert_eq!(r.to_string(), "No .DS_Store files found");
+        let result = |found, deleted, failures: Vec<KillFailure>| KillResult {
+            found,
+            deleted,
+            duration: Duration::ZERO,
+            files: vec![],
+            failures,
+            protected: vec![],
+            skipped: vec![],
+            stats: Stats::default(),
+        };
+
+        assert_eq!(result(0, 0, vec![]).to_string(), "No .DS_Store files found");
+        assert_eq!(result(5, 0, vec![]).to_string(), "Found 5 .DS_Store file(s)");
+        assert_eq!(result(5, 5, vec![]).to_string(), "Deleted 5 .DS_Store file(s)");
 
-        let r = KillResult { found: 5, deleted: 0, duration: Duration::ZERO };
-        assert_eq!(r.to_string(), "Found 5 .DS_Store file(s)");
+        let failed = vec![KillFailure {
+            path: PathBuf::from("/ro/.DS_Store"),
+            kind: io::ErrorKind::PermissionDenied,
+        }];
+        assert_eq!(result(5, 4, failed).to_string(), "Deleted 4 .DS_Store file(s), 1 failed");
 
-        let r = KillResult { found: 5, deleted: 5, duration: Duration::ZERO };
-        assert_eq!(r.to_string(), "Deleted 5 .DS_Store file(s)");
+        let mut r = result(0, 0, vec![]);
+        r.protected.push((PathBuf::from("/dmg/.DS_Store"), Reason::DmgLayout));
+        assert_eq!(r.to_string(), "No .DS_Store files to delete, 1 protected");
+
+        // Folders are not counted as .DS_Store files
+        let mut r = result(3, 3, vec![]);
+        r.stats.by_type = vec![(Target::DsStore, 2), (Target::Macosx, 1)];
+        assert_eq!(r.to_string(), "Deleted 3 target(s) (2 .DS_Store, 1 __MACOSX)");
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let mut r = KillResult {
+            found: 0,
+            deleted: 0,
+            duration: Duration::ZERO,
+            files: vec![],
+            failures: vec![],
+            protected: vec![],
+            skipped: vec![],
+            stats: Stats::default(),
+        };
+        assert_eq!(r.exit_code(true), EXIT_OK);
+        assert_eq!(r.exit_code(false), EXIT_OK);
+
+        r.skipped.push(PathBuf::from("/repo/.DS_Store"));
+        assert_eq!(r.exit_code(true), EXIT_FOUND);
+        assert_eq!(r.exit_code(false), EXIT_GIT_SKIPPED);
+
+        r.skipped.clear();
+        r.found = 1;
+        assert_eq!(r.exit_code(true), EXIT_FOUND);
+
+        r.skipped.push(PathBuf::from("/repo/.DS_Store"));
+
+        r.failures.push(KillFailure {
+            path: PathBuf::from("/ro/.DS_Store"),
+            kind: io::ErrorKind::PermissionDenied,
+        });
+        assert_eq!(r.exit_code(false), EXIT_PARTIAL);
+    }
+
+    #[test]
+    fn test_failures_by_kind() {
+        let fail = |p: &str, kind| KillFailure { path: PathBuf::from(p), kind };
+        let r = KillResult {
+            found: 3,
+            deleted: 0,
+            duration: Duration::ZERO,
+            files: vec![],
+            failures: vec![
+                fail("/a", io::ErrorKind::ReadOnlyFilesystem),
+                fail("/b", io::ErrorKind::PermissionDenied),
+                fail("/c", io::ErrorKind::PermissionDenied),
+            ],
+            protected: vec![],
+            skipped: vec![],
+            stats: Stats::default(),
+        };
+        assert_eq!(
+            r.failures_by_kind(),
+            vec![(io::ErrorKind::PermissionDenied, 2), (io::ErrorKind::ReadOnlyFilesystem, 1)]
+        );
+    }
+
+    #[test]
+    fn test_kill_result_json() {
+        let r = KillResult {
+            found: 2,
+            deleted: 1,
+            duration: Duration::from_millis(12),
+            files: vec![PathBuf::from("/a/.DS_Store")],
+            failures: vec![KillFailure {
+                path: PathBuf::from("/ro/.DS_Store"),
+                kind: io::ErrorKind::PermissionDenied,
+            }],
+            protected: vec![(PathBuf::from("/x.app/Contents/Resources/.DS_Store"), Reason::AppResources)],
+            skipped: vec![PathBuf::from("/repo/.DS_Store")],
+            stats: Stats::default(),
+        };
+        assert_eq!(
+            r.to_json(false),
+            concat!(
+                r#"{"found":3,"deleted":1,"skipped":1,"protected":1,"errors":1,"#,
+                r#""errors_by_kind":{"permission denied":1},"dry_run":false,"duration_ms":12,"#,
+                r#""files":[{"path":"/a/.DS_Store","status":"deleted"},"#,
+                r#"{"path":"/repo/.DS_Store","status":"skipped-git"},"#,
+                r#"{"path":"/ro/.DS_Store","status":"error","error":"permission denied"},"#,
+                r#"{"path":"/x.app/Contents/Resources/.DS_Store","status":"protected","#,
+                r#""reason":"app bundle resource"}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_kill_files_missing_is_not_failure() {
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let gone = dir.path().join(TARGET_FILE);
+
+        let result = kill_files(&[gone], &KillOptions::new());
+
+        assert_eq!(result.found, 1);
+        assert_eq!(result.deleted, 0);
+        assert!(result.failures.is_empty());
     }
 
     #[test]
@@ -229,8 +1381,9 @@ mod tests {
         let excludes = vec!["node_modules".to_string()];
 
         // Test scan (recursive)
+        let scanner = Scanner::new(path).recursive(true).excludes(&excludes);
         let mut found = Vec::new();
-        let count = scan_streaming(path, true, &excludes, |p| found.push(p.to_path_buf()));
+        let count = scanner.for_each(|p| found.push(p.to_path_buf()));
 
         assert_eq!(count, 2, "Should find 2 .DS_Store files (root + subdir)");
         assert!(found.iter().any(|p| p.parent().unwrap() == path));
@@ -238,21 +1391,269 @@ mod tests {
         assert!(!found.iter().any(|p| p.parent().unwrap() == node_modules));
 
         // Test kill dry-run
-        let opts = KillOptions { dry_run: true, quiet: true };
-        let result = kill_streaming(path, true, &excludes, &opts);
+        let result = scanner.kill(&KillOptions::new().dry_run(true));
 
         assert_eq!(result.found, 2);
         assert_eq!(result.deleted, 0);
         assert!(path.join(TARGET_FILE).exists(), "Dry-run should not delete");
 
         // Test kill actual
-        let opts = KillOptions { dry_run: false, quiet: true };
-        let result = kill_streaming(path, true, &excludes, &opts);
+        let result = scanner.kill(&KillOptions::new());
 
         assert_eq!(result.found, 2);
         assert_eq!(result.deleted, 2);
+        assert!(result.failures.is_empty());
         assert!(!path.join(TARGET_FILE).exists(), "Should be deleted");
         assert!(!subdir.join(TARGET_FILE).exists(), "Should be deleted");
         assert!(node_modules.join(TARGET_FILE).exists(), "Excluded should remain");
     }
+
+    #[test]
+    fn test_kill_keeps_protected() {
+        use std::fs::File;
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let dmg = dir.path().join("dmg");
+        fs::create_dir_all(dmg.join(".background")).unwrap();
+        File::create(dmg.join(TARGET_FILE)).unwrap();
+        File::create(dir.path().join(TARGET_FILE)).unwrap();
+
+        let result = Scanner::new(dir.path()).recursive(true).kill(&KillOptions::new());
+
+        assert_eq!(result.found, 1);
+        assert_eq!(result.deleted, 1);
+        assert_eq!(result.protected, vec![(dmg.join(TARGET_FILE), Reason::DmgLayout)]);
+        assert!(dmg.join(TARGET_FILE).exists());
+    }
+
+    /// Records every observer call as a short line
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl KillObserver for Recorder {
+        fn on_found(&mut self, path: &Path) {
+            self.0.push(format!("found {}", path.parent().unwrap().file_name().unwrap().to_string_lossy()));
+        }
+        fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+            let dir = path.parent().unwrap().file_name().unwrap().to_string_lossy();
+            self.0.push(format!("skipped {} {:?}", dir, why));
+        }
+        fn on_deleted(&mut self, path: &Path, dry_run: bool) {
+            let dir = path.parent().unwrap().file_name().unwrap().to_string_lossy();
+            self.0.push(format!("deleted {} {}", dir, dry_run));
+        }
+        fn on_finished(&mut self, result: &KillResult) {
+            self.0.push(format!("finished {}", result.deleted));
+        }
+    }
+
+    #[test]
+    fn test_observers() {
+        use std::fs::{self, File};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        for sub in ["keep", "big", "plain"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        File::create(dir.path().join("keep").join(TARGET_FILE)).unwrap();
+        fs::write(dir.path().join("big").join(TARGET_FILE), [0u8; 100]).unwrap();
+        File::create(dir.path().join("plain").join(TARGET_FILE)).unwrap();
+
+        let filter = Filter { max_size: Some(10), ..Default::default() };
+        let opts = KillOptions::new().protect(Protector::new(&["keep".into()]));
+        let mut first = Recorder::default();
+        let mut second: Option<Recorder> = None;
+        let result = Scanner::new(dir.path())
+            .recursive(true)
+            .filter(filter)
+            .kill_with(&opts, &mut (&mut first, &mut second));
+
+        assert_eq!(result.deleted, 1);
+        let mut events = first.0;
+        let last = events.pop();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                "deleted plain false",
+                "found keep",
+                "found plain",
+                "skipped big Filtered(Large(100))",
+                r#"skipped keep Protected(Keep("keep"))"#,
+            ]
+        );
+        assert_eq!(last.as_deref(), Some("finished 1"));
+    }
+
+    #[test]
+    fn test_macosx_folders() {
+        use std::fs::{self, File};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let junk = dir.path().join("a").join(MACOSX_DIR);
+        let mixed = dir.path().join("b").join(MACOSX_DIR);
+        fs::create_dir_all(junk.join("docs")).unwrap();
+        fs::create_dir_all(&mixed).unwrap();
+        File::create(junk.join("._a.txt")).unwrap();
+        File::create(junk.join("docs").join("._b.txt")).unwrap();
+        File::create(mixed.join("._a.txt")).unwrap();
+        File::create(mixed.join("notes.txt")).unwrap();
+        File::create(dir.path().join(MACOSX_DIR)).unwrap(); // a file, not a folder
+
+        assert!(is_macosx_dir(&junk));
+        assert!(!is_macosx_dir(&dir.path().join(MACOSX_DIR)));
+        assert_eq!(macosx_size(&junk), Ok(0));
+        assert_eq!(macosx_size(&mixed), Err(mixed.join("notes.txt")));
+
+        // Off by default
+        assert!(Scanner::new(dir.path()).recursive(true).scan().is_empty());
+
+        let scanner = Scanner::new(dir.path()).recursive(true).macosx(true);
+        let mut found = scanner.scan();
+        found.sort();
+        assert_eq!(found, vec![junk.clone(), mixed.clone()]);
+        assert_eq!(Scanner::new(dir.path().join("a")).macosx(true).scan(), vec![junk.clone()]);
+
+        // Filters apply to the folder as a whole
+        fs::write(junk.join("._a.txt"), [0u8; 5]).unwrap();
+        let small = Filter { max_size: Some(4), ..Filter::default() };
+        let filtered = scanner.clone().filter(small).kill(&KillOptions::new().dry_run(true));
+        // Only the 5-byte folder is too large; the other one is still foreign
+        assert_eq!((filtered.found, filtered.stats.filtered), (0, 1));
+
+        let dry = scanner.kill(&KillOptions::new().dry_run(true));
+        assert_eq!((dry.found, dry.files), (1, vec![junk.clone()]));
+        assert!(junk.exists());
+
+        let mut rec = Recorder::default();
+        let result = scanner.kill_with(&KillOptions::new(), &mut rec);
+        assert_eq!((result.found, result.deleted), (1, 1));
+        assert!(!junk.exists());
+        assert!(mixed.join("notes.txt").exists());
+        assert!(rec.0.iter().any(|e| e.starts_with("skipped b Foreign")));
+    }
+
+    #[test]
+    fn test_parallel_kill() {
+        use std::fs::{self, File};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let files: Vec<PathBuf> = (0..40)
+            .map(|i| {
+                let d = dir.path().join(format!("d{:02}", i));
+                fs::create_dir(&d).unwrap();
+                File::create(d.join(TARGET_FILE)).unwrap();
+                d.join(TARGET_FILE)
+            })
+            .collect();
+
+        // Ordered output matches submission order, whatever finishes first
+        let dry = kill_files(&files, &KillOptions::new().dry_run(true).jobs(4).ordered(true));
+        assert_eq!(dry.files, files);
+
+        let scanner = Scanner::new(dir.path()).recursive(true);
+        let mut rec = Recorder::default();
+        let result = scanner.kill_with(&KillOptions::new().jobs(4), &mut rec);
+        assert_eq!((result.found, result.deleted), (40, 40));
+        assert_eq!(result.stats.dirs, 41);
+        assert_eq!(rec.0.iter().filter(|e| e.starts_with("deleted")).count(), 40);
+        assert_eq!(rec.0.last().map(String::as_str), Some("finished 40"));
+        assert!(files.iter().all(|f| !f.exists()));
+    }
+
+    #[test]
+    fn test_stats() {
+        use std::fs::{self, File};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        for sub in ["a", "big", "node_modules/pkg/deep", "x/__MACOSX"] {
+            fs::create_dir_all(dir.path().join(sub)).unwrap();
+        }
+        fs::write(dir.path().join("a").join(TARGET_FILE), [0u8; 10]).unwrap();
+        fs::write(dir.path().join("big").join(TARGET_FILE), [0u8; 100]).unwrap();
+        File::create(dir.path().join("node_modules/pkg/deep").join(TARGET_FILE)).unwrap();
+        fs::write(dir.path().join("x/__MACOSX/._a"), [0u8; 5]).unwrap();
+
+        let result = Scanner::new(dir.path())
+            .recursive(true)
+            .exclude("node_modules")
+            .filter(Filter { max_size: Some(50), ..Default::default() })
+            .macosx(true)
+            .kill(&KillOptions::new().dry_run(true));
+        let s = &result.stats;
+
+        // root, a, big, x read; node_modules pruned; __MACOSX not entered
+        assert_eq!((s.dirs, s.pruned, s.filtered), (4, 1, 1));
+        assert_eq!(s.entries, 7);
+        assert_eq!(s.bytes, 15);
+        let mut by_type = s.by_type.clone();
+        by_type.sort_by_key(|(t, _)| t.name());
+        assert_eq!(by_type, vec![(Target::DsStore, 1), (Target::Macosx, 1)]);
+        assert_eq!(s.cache_hit, None);
+
+        let json = result.stats_json();
+        assert!(json.starts_with(r#"{"dirs":4,"entries":7,"pruned":1,"found_by_type":{"#));
+        assert!(json.contains(r#""bytes":15,"skipped_git":0,"skipped_protected":0,"skipped_filter":1,"errors":0"#));
+        assert!(json.ends_with(r#""cache_hit":null}"#));
+    }
+
+    #[test]
+    fn test_follow_symlinks() {
+        use std::{fs::{self, File}, os::unix::fs::symlink};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        File::create(dir.path().join("a").join(TARGET_FILE)).unwrap();
+        File::create(other.path().join(TARGET_FILE)).unwrap();
+        symlink(other.path(), dir.path().join("ext")).unwrap();
+        symlink(dir.path().join("a"), dir.path().join("twin")).unwrap();
+        symlink(dir.path(), dir.path().join("a").join("loop")).unwrap();
+
+        let scanner = Scanner::new(dir.path()).recursive(true);
+        assert_eq!(scanner.scan(), vec![dir.path().join("a").join(TARGET_FILE)]);
+
+        // `twin` and `loop` lead to directories already read
+        let follow = Traversal { follow_symlinks: true, ..Default::default() };
+        let mut found = scanner.traversal(follow).scan();
+        found.sort();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], dir.path().join("ext").join(TARGET_FILE));
+        assert!(found[1].ends_with(TARGET_FILE) && !found[1].starts_with(dir.path().join("ext")));
+    }
+
+    #[test]
+    fn test_depth_limits() {
+        use std::fs::{self, File};
+        use tempfile::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+        for sub in ["", "a", "a/b", "a/b/c"] {
+            File::create(dir.path().join(sub).join(TARGET_FILE)).unwrap();
+        }
+        let depths = |min, max| {
+            let limits = Traversal { min_depth: min, max_depth: max, ..Default::default() };
+            // A depth limit alone makes the walk recursive
+            let (mut found, stats) = Scanner::new(dir.path()).traversal(limits).scan_stats(&mut ());
+            found.sort();
+            let found: Vec<usize> = found
+                .iter()
+                .map(|p| p.strip_prefix(dir.path()).unwrap().components().count())
+                .collect();
+            (found, stats.dirs)
+        };
+
+        assert_eq!(depths(None, Some(1)), (vec![1], 1));
+        // a/b is never read
+        assert_eq!(depths(None, Some(2)), (vec![1, 2], 2));
+        assert_eq!(depths(Some(2), Some(3)), (vec![2, 3], 3));
+        assert_eq!(depths(Some(3), None), (vec![3, 4], 4));
+    }
 }
@@ -0,0 +1,64 @@
+//! Find and delete `.DS_Store` files
+//!
+//! The `dsk` binary is a thin client over this crate. The stable API is the
+//! scanning and killing core:
+//!
+//! - [`Scanner`] finds targets under a directory and can delete them
+//! - [`KillOptions`] / [`KillResult`] control and report a deletion, and
+//!   [`Stats`] breaks down what it looked at and where the time went
+//! - [`is_target`] and [`is_excluded`] match targets and exclude patterns
+//! - [`Filter`] restricts targets by age and size, and [`Traversal`] limits
+//!   which directories a walk enters
+//! - [`Protector`] leaves intentional `.DS_Store` files alone
+//! - [`git`] checks which files are tracked by git
+//! - [`archive`] removes the same targets from zip and tar archives
+//! - [`KillObserver`] receives each step as it happens; [`report`] has the
+//!   printer, JSON summary and notification observers `dsk` uses
+//!
+//! ```no_run
+//! use ds_store_killer::{KillOptions, Scanner, git};
+//!
+//! let files = Scanner::new("/Users/me/Projects").recursive(true).scan();
+//! let safety = git::check_files(&files);
+//! let result = ds_store_killer::kill_files(&safety.safe, &KillOptions::new());
+//! println!("{}", result);
+//! ```
+//!
+//! The remaining modules support the `dsk` binary and may change at any time.
+
+pub mod archive;
+pub mod git;
+pub mod killer;
+pub mod protect;
+pub mod report;
+
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod consts;
+#[doc(hidden)]
+pub mod ctl;
+#[doc(hidden)]
+pub mod debounce;
+#[doc(hidden)]
+pub mod engine;
+#[doc(hidden)]
+pub mod json;
+#[doc(hidden)]
+pub mod log;
+#[doc(hidden)]
+pub mod pause;
+#[doc(hidden)]
+pub mod poll;
+#[doc(hidden)]
+pub mod watcher;
+#[doc(hidden)]
+pub mod xml;
+
+pub use killer::{
+    Filter, KillFailure, KillObserver, KillOptions, KillResult, Scanner, Skip, Skipped, Stats,
+    Target, Traversal, is_excluded, is_target, kill_files, kill_files_with,
+};
+pub use protect::{Protector, Reason};
@@ -0,0 +1,59 @@
+//! Target lists for `dsk kill --from-file` / `--stdin`
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Read},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+/// Split a newline- or NUL-separated list into paths
+///
+/// Relative entries are taken relative to `base`; blank entries and a
+/// trailing `\r` (lists written on Windows) are dropped.
+pub fn parse(data: &[u8], null: bool, base: &Path) -> Vec<PathBuf> {
+    let sep = if null { b'\0' } else { b'\n' };
+    data.split(|&b| b == sep)
+        .map(|entry| if null { entry } else { entry.strip_suffix(b"\r").unwrap_or(entry) })
+        .filter(|entry| !entry.is_empty())
+        // Collecting the components drops `./` and doubled slashes
+        .map(|entry| base.join(OsStr::from_bytes(entry)).components().collect())
+        .collect()
+}
+
+/// Read the list from `file`, or standard input when there is none
+pub fn read(file: Option<&Path>, null: bool, base: &Path) -> Result<Vec<PathBuf>, String> {
+    let data = match file {
+        Some(file) => fs::read(file).map_err(|e| format!("{}: {}", file.display(), e))?,
+        None => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map_err(|e| format!("stdin: {}", e))?;
+            data
+        }
+    };
+    Ok(parse(&data, null, base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let base = Path::new("/work");
+        let lines = parse(b"a/.DS_Store\r\n\n./b\n/abs dir\n", false, base);
+        assert_eq!(
+            lines,
+            vec![
+                PathBuf::from("/work/a/.DS_Store"),
+                PathBuf::from("/work/b"),
+                PathBuf::from("/abs dir"),
+            ]
+        );
+
+        // NUL-separated entries may contain newlines
+        let nul = parse(b"odd\nname\0b\0", true, base);
+        assert_eq!(nul, vec![PathBuf::from("/work/odd\nname"), PathBuf::from("/work/b")]);
+    }
+}
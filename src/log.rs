@@ -1,8 +1,25 @@
 //! Colored logging utilities
 
 use colored::Colorize;
-use std::path::Path;
-use shellexpand;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// When set, human-readable stdout output is suppressed so that stdout
+/// carries only machine-readable (JSON) output. Warnings and errors still
+/// go to stderr.
+static MACHINE: AtomicBool = AtomicBool::new(false);
+
+/// Switch stdout to machine-readable mode
+pub fn set_machine(on: bool) {
+    MACHINE.store(on, Ordering::Relaxed);
+}
+
+#[inline]
+fn machine() -> bool {
+    MACHINE.load(Ordering::Relaxed)
+}
 
 /// Shorten path by replacing home directory with ~
 pub fn shorten_path(path: &Path) -> String {
@@ -31,11 +48,17 @@ fn format_path(path: &Path) -> String {
 
 #[inline]
 pub fn ok(msg: &str) {
+    if machine() {
+        return;
+    }
     println!("{} {}", "[ok]".green(), msg);
 }
 
 #[inline]
 pub fn info(msg: &str) {
+    if machine() {
+        return;
+    }
     println!("{} {}", "[info]".blue(), msg);
 }
 
@@ -51,21 +74,51 @@ pub fn error(msg: &str) {
 
 #[inline]
 pub fn kill(path: &Path) {
+    if machine() {
+        return;
+    }
     println!("{} {}", "[kill]".red(), format_path(path));
 }
 
 #[inline]
 pub fn dry(path: &Path) {
+    if machine() {
+        return;
+    }
     println!("{} {}", "[dry]".magenta(), format_path(path));
 }
 
+/// Report a target left alone by a protection rule
+#[inline]
+pub fn keep(path: &Path, reason: &impl std::fmt::Display) {
+    if machine() {
+        return;
+    }
+    println!("{} {} ({})", "[keep]".green(), format_path(path), reason);
+}
+
 #[inline]
 pub fn watch(msg: &str) {
+    if machine() {
+        return;
+    }
     println!("{} {}", "[watch]".cyan(), msg);
 }
 
 /// Display a found path with filename highlighted
 #[inline]
-pub fn found(path: &std::path::Path) {
+pub fn found(path: &Path) {
+    if machine() {
+        return;
+    }
     println!("  {}", format_path(path));
 }
+
+/// Display an indented plain detail line
+#[inline]
+pub fn detail(msg: &str) {
+    if machine() {
+        return;
+    }
+    println!("  {}", msg);
+}
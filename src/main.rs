@@ -1,95 +1,347 @@
-mod cache;
+mod check;
 mod cli;
-mod consts;
-mod git;
-mod killer;
-mod log;
+mod list;
 mod service;
-mod watcher;
 
 use clap::Parser;
-use cli::{Cli, Commands, KillArgs, ServiceAction, WatchArgs};
-use killer::KillOptions;
-use std::{io::{self, Write}, path::Path};
+use cli::{
+    ArchiveAction, CheckArgs, Cli, Commands, ConfigAction, CtlAction, KillArgs, KillFormat, ServiceAction,
+    WatchArgs, WatchFormat,
+};
+use consts::*;
+use ds_store_killer::{
+    KillOptions, KillResult, Scanner, Stats, archive, cache, config, consts, ctl, git, is_excluded,
+    is_target, killer, log, pause, protect, report, watcher, xml,
+};
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
 fn main() {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Kill { args } => run_kill(args),
-        Commands::Watch { args } => run_watch(&args),
-        Commands::Service { action } => run_service(action),
-    }
+    let config = cli.config.as_deref();
+    let code = match cli.command {
+        Commands::Kill { args } => run_kill(args, config),
+        Commands::Watch { args } => run_watch(args, config),
+        Commands::Check { args } => run_check(args, config),
+        Commands::Ctl { socket, action } => run_ctl(socket.as_deref(), action),
+        Commands::Pause { path, duration, socket } => run_pause(&path, duration, socket.as_deref()),
+        Commands::Resume { path, socket } => run_resume(&path, socket.as_deref()),
+        Commands::Archive { action } => run_archive(action),
+        Commands::Config { action } => run_config(action, config),
+        Commands::Service { action } => run_service(action, config),
+    };
+    std::process::exit(code);
 }
 
-fn run_kill(args: KillArgs) {
-    let path = shellexpand::tilde(&args.path.to_string_lossy()).to_string();
+/// Expand `~`, canonicalize and check that the target is a directory
+fn resolve_dir(path: &Path) -> Option<PathBuf> {
+    let path = shellexpand::tilde(&path.to_string_lossy()).to_string();
     let path = Path::new(&path);
     let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
 
     if !path.is_dir() {
         log::error(&format!("Not a directory: {}", path.display()));
-        std::process::exit(1);
+        return None;
     }
+    Some(path)
+}
 
-    let opts = KillOptions {
-        dry_run: args.dry_run,
-        quiet: args.quiet,
-    };
+/// Merge config files and `DSK_*` variables that apply to `dir` under the
+/// command-line `flags`
+fn configure(config: Option<&Path>, dir: &Path, flags: config::Layer) -> Option<config::Layer> {
+    config::resolve(config, dir, flags)
+        .map_err(|e| log::error(&format!("Invalid configuration: {}", e)))
+        .ok()
+}
+
+fn run_kill(mut args: KillArgs, config: Option<&Path>) -> i32 {
+    if args.from_file.is_some() || args.stdin {
+        return run_kill_list(args, config);
+    }
+
+    let Some(path) = resolve_dir(&args.path) else { return EXIT_ERROR };
+    let Some(cfg) = configure(config, &path, args.flags()) else { return EXIT_ERROR };
+    args.apply(&cfg);
+
+    if args.format == KillFormat::Json {
+        return run_kill_json(&path, &args);
+    }
+
+    let opts = kill_options(&args).ordered(args.ordered);
+    let notifier = args.notify.then_some(report::Notifier);
 
     // Fast path: -y flag means streaming mode (no confirmation needed)
     if args.yes {
-        let result = killer::kill_streaming(&path, args.recursive, &args.exclude, &opts);
+        let mut observer = (report::Printer::new(args.quiet), notifier);
+        let result = Scanner::new(&path)
+            .recursive(args.recursive)
+            .excludes(&args.exclude)
+            .filter(args.filter.to_filter())
+            .traversal(args.traversal.to_traversal())
+            .macosx(args.macosx)
+            .kill_with(&opts.skip_tracked(skip_tracked(&args)), &mut observer);
 
         if !args.dry_run && result.deleted > 0 {
             cache::invalidate(&path, args.recursive);
         }
-
-        if result.found == 0 {
-            log::info("No .DS_Store files found");
-        } else if args.dry_run {
-            log::info(&format!("Dry-run: {} file(s) would be deleted", result.found));
-        } else {
-            log::ok(&format!("Deleted {} .DS_Store file(s)", result.deleted));
-        }
-
-        if args.stats {
-            println!("  Time: {:?}", result.duration);
-        }
-        return;
+        return finish_streamed(&result, &args);
     }
 
     // Interactive path: try cache first
-    let files = if let Some(cached) = cache::load_verified(&path, args.recursive) {
+    let cacheable = cacheable(&args);
+    let cached = cacheable.then(|| cache::load_verified(&path, args.recursive)).flatten();
+    let hit = cached.is_some();
+    let (files, mut walked) = if let Some(cached) = cached {
         if cached.is_empty() {
             log::info("No .DS_Store files found (cached)");
-            return;
+            return EXIT_OK;
         }
         log::info(&format!("Found {} file(s) (cached)", cached.len()));
         for f in &cached {
             log::found(f);
         }
-        cached
+        (cached, Stats::default())
     } else {
         log::info("Scanning for .DS_Store files...");
-        scan_and_cache(&path, args.recursive, &args.exclude)
+        scan_and_cache(&path, &args)
     };
+    walked.cache_hit = cacheable.then_some(hit);
 
-    if files.is_empty() {
+    let invalidate = || cache::invalidate(&path, args.recursive);
+    kill_confirmed(files, walked, &args, opts, invalidate)
+}
+
+/// Kill targets named on a `--from-file`/`--stdin` list
+///
+/// Listed directories are scanned like `dsk kill DIR`; listed files are
+/// taken as they are, as long as they are targets.
+fn run_kill_list(mut args: KillArgs, config: Option<&Path>) -> i32 {
+    let base = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let entries = match list::read(args.from_file.as_deref(), args.null, &base) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error(&format!("Cannot read the target list: {}", e));
+            return EXIT_ERROR;
+        }
+    };
+    let Some(cfg) = configure(config, &base, args.flags()) else { return EXIT_ERROR };
+    args.apply(&cfg);
+
+    let json = args.format == KillFormat::Json;
+    if json {
+        log::set_machine(true);
+        if !args.yes && !args.dry_run {
+            log::error("--format json requires --yes or --dry-run");
+            return EXIT_ERROR;
+        }
+    } else {
+        log::info("Scanning for .DS_Store files...");
+    }
+    let (files, dirs, walked) = collect_list(&entries, &args);
+    let invalidate = || {
+        for dir in &dirs {
+            cache::invalidate(dir, args.recursive);
+        }
+        // Explicit files change their directory's non-recursive listing
+        for parent in files.iter().filter_map(|f| f.parent()) {
+            cache::invalidate(parent, false);
+        }
+    };
+
+    if json || args.yes {
+        let opts = kill_options(&args).skip_tracked(skip_tracked(&args));
+        let notifier = args.notify.then_some(report::Notifier);
+        let mut result = if json {
+            let summary =
+                report::JsonSummary::new(args.dry_run).stats(args.stats).walked(walked.clone());
+            killer::kill_files_with(&files, &opts.ordered(true), &mut (summary, notifier))
+        } else {
+            let printer = report::Printer::new(args.quiet);
+            killer::kill_files_with(&files, &opts.ordered(args.ordered), &mut (printer, notifier))
+        };
+        if !args.dry_run && result.deleted > 0 {
+            invalidate();
+        }
+        if json {
+            return result.exit_code(args.dry_run);
+        }
+        result.duration += walked.walk;
+        result.stats.add(&walked);
+        return finish_streamed(&result, &args);
+    }
+
+    for f in &files {
+        log::found(f);
+    }
+    let opts = kill_options(&args).ordered(args.ordered);
+    kill_confirmed(files.clone(), walked, &args, opts, invalidate)
+}
+
+/// Targets on a list, the directories that were scanned for them, and what
+/// the scans saw
+fn collect_list(entries: &[PathBuf], args: &KillArgs) -> (Vec<PathBuf>, Vec<PathBuf>, Stats) {
+    let start = Instant::now();
+    let filter = args.filter.to_filter();
+    let (mut files, mut dirs, mut stats) = (Vec::new(), Vec::new(), Stats::default());
+    let mut ignored = 0;
+
+    for path in entries {
+        let Ok(meta) = path.symlink_metadata() else {
+            log::warn(&format!("Not found: {}", log::shorten_path(path)));
+            continue;
+        };
+        if is_excluded(path, &args.exclude) {
+            continue;
+        }
+        let explicit = if args.macosx && killer::is_macosx_dir(path) {
+            true
+        } else if path.is_dir() {
+            let (found, scanned) = Scanner::new(path)
+                .recursive(args.recursive)
+                .excludes(&args.exclude)
+                .filter(filter.clone())
+                .traversal(args.traversal.to_traversal())
+                .macosx(args.macosx)
+                .scan_stats(&mut ());
+            files.extend(found);
+            stats.add(&scanned);
+            dirs.push(path.clone());
+            continue;
+        } else {
+            !meta.is_dir() && is_target(path)
+        };
+
+        if !explicit {
+            ignored += 1;
+        } else if filter.check(path).is_ok() {
+            files.push(path.clone());
+        } else {
+            stats.filtered += 1;
+        }
+    }
+
+    // A file may be listed and also found under a listed directory
+    files.sort();
+    files.dedup();
+    stats.walk = start.elapsed();
+    if ignored > 0 && !args.quiet {
+        log::info(&format!("Ignored {} listed path(s) that are not targets", ignored));
+    }
+    (files, dirs, stats)
+}
+
+/// Options shared by every kill mode
+fn kill_options(args: &KillArgs) -> KillOptions {
+    KillOptions::new()
+        .dry_run(args.dry_run)
+        .protect(protect::Protector::new(&args.keep))
+        .jobs(jobs(args.jobs))
+}
+
+/// Whether to leave git-tracked targets alone, warning if git is missing
+fn skip_tracked(args: &KillArgs) -> bool {
+    let skip = !args.force && git::is_available();
+    if !args.force && !skip {
+        log::warn("git not found - cannot check for tracked files");
+    }
+    skip
+}
+
+/// Summary lines and exit code after a kill without confirmation
+fn finish_streamed(result: &KillResult, args: &KillArgs) -> i32 {
+    if result.found == 0 && !result.protected.is_empty() {
+        let kept = result.protected.len();
+        log::info(&format!("Nothing to delete: all {} file(s) are protected", kept));
+    } else if result.found == 0 {
         log::info("No .DS_Store files found");
-        return;
+    } else if args.dry_run {
+        log::info(&format!("Dry-run: {} file(s) would be deleted", result.found));
+    } else {
+        log::ok(&format!("Deleted {}", result.stats.describe(result.deleted)));
+    }
+    if result.found > 0 && !result.protected.is_empty() {
+        log::info(&format!("Kept {} protected file(s)", result.protected.len()));
+    }
+    if !result.skipped.is_empty() {
+        log::warn(&format!(
+            "Skipped {} git-tracked file(s) (use --force to delete)",
+            result.skipped.len()
+        ));
+    }
+
+    report_failures(result, args.quiet);
+
+    if args.stats {
+        print!("{}", report::stats(result));
+    }
+
+    result.exit_code(args.dry_run)
+}
+
+/// Report found `files`, check them against git and delete them once
+/// confirmed; `walked` is what finding them took
+fn kill_confirmed(
+    files: Vec<PathBuf>,
+    mut walked: Stats,
+    args: &KillArgs,
+    opts: KillOptions,
+    invalidate: impl FnOnce(),
+) -> i32 {
+    let notifier = args.notify.then_some(report::Notifier);
+
+    // Protected files are reported once here and never offered for deletion
+    let mut protected = Vec::new();
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|f| match opts.protect.check(f) {
+            Some(reason) => {
+                if !args.quiet {
+                    log::keep(f, &reason);
+                }
+                protected.push((f.clone(), reason));
+                false
+            }
+            None => true,
+        })
+        .collect();
+    if files.is_empty() {
+        if protected.is_empty() {
+            log::info("No .DS_Store files found");
+        } else {
+            log::info(&format!("Nothing to delete: all {} file(s) are protected", protected.len()));
+        }
+        return EXIT_OK;
+    }
+    if !protected.is_empty() {
+        log::info(&format!("Kept {} protected file(s)", protected.len()));
     }
 
     log::info(&format!("Found {} file(s)", files.len()));
 
     if args.dry_run {
         log::info(&format!("Dry-run: {} file(s) would be deleted", files.len()));
-        return;
+        if args.stats {
+            let mut result = killer::kill_files(&files, &opts.dry_run(true));
+            result.duration += walked.walk;
+            result.stats.add(&walked);
+            result.protected.extend(protected);
+            print!("{}", report::stats(&result));
+        }
+        return EXIT_FOUND;
     }
 
     // Git safety check (only if git is available)
+    let mut skipped = Vec::new();
     let files_to_delete = if git::is_available() {
+        let start = Instant::now();
         let safety = git::check_files(&files);
+        walked.git = start.elapsed();
 
         if !safety.tracked.is_empty() {
             if args.force {
@@ -108,7 +360,12 @@ fn run_kill(args: KillArgs) {
             }
         }
 
-        if args.force { files.clone() } else { safety.safe }
+        if args.force {
+            files.clone()
+        } else {
+            skipped = safety.tracked;
+            safety.safe
+        }
     } else {
         // Git not available - warn once and proceed without safety check
         log::warn("git not found - cannot check for tracked files");
@@ -117,79 +374,405 @@ fn run_kill(args: KillArgs) {
 
     if files_to_delete.is_empty() {
         log::info("No files to delete (all are git-tracked, use --force)");
-        return;
+        return EXIT_GIT_SKIPPED;
     }
 
     let msg = format!("Delete {} file(s)?", files_to_delete.len());
-    if !confirm(&msg) {
+    // Standard input already held the list
+    if !confirm(&msg, args.stdin) {
         log::info("Cancelled");
-        return;
+        return EXIT_CANCELLED;
     }
 
-    let result = killer::kill_files(&files_to_delete, &KillOptions {
-        dry_run: false,
-        quiet: true,
-    });
+    // Quiet printer: only warnings, e.g. a __MACOSX folder with real files
+    let mut observer = (report::Printer::new(true), notifier);
+    let mut result = killer::kill_files_with(&files_to_delete, &opts.dry_run(false), &mut observer);
 
-    cache::invalidate(&path, args.recursive);
-    log::ok(&format!("Deleted {} .DS_Store file(s)", result.deleted));
+    invalidate();
+    log::ok(&format!("Deleted {}", result.stats.describe(result.deleted)));
+    report_failures(&result, args.quiet);
 
+    // Fold in what was decided before the prompt
+    result.duration += walked.walk + walked.git;
+    result.stats.add(&walked);
+    result.protected.extend(protected);
+    result.skipped.extend(skipped);
     if args.stats {
-        println!("  Time: {:?}", result.duration);
+        print!("{}", report::stats(&result));
     }
+
+    result.exit_code(false)
 }
 
-fn run_watch(args: &WatchArgs) {
-    let path = shellexpand::tilde(&args.path.to_string_lossy()).to_string();
-    let path = Path::new(&path);
-    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+/// Print a per-cause breakdown of failed deletions to stderr
+fn report_failures(result: &KillResult, quiet: bool) {
+    if result.failures.is_empty() {
+        return;
+    }
 
-    if !path.is_dir() {
-        log::error(&format!("Not a directory: {}", path.display()));
-        std::process::exit(1);
+    let causes: Vec<String> = result
+        .failures_by_kind()
+        .iter()
+        .map(|(kind, n)| format!("{} {}", n, kind))
+        .collect();
+    log::error(&format!(
+        "Failed to delete {} file(s): {}",
+        result.failures.len(),
+        causes.join(", ")
+    ));
+
+    if !quiet {
+        for f in &result.failures {
+            log::warn(&format!("{}: {}", f.kind, log::shorten_path(&f.path)));
+        }
+    }
+}
+
+/// Worker count for `--jobs`, defaulting to the number of CPUs
+fn jobs(requested: Option<usize>) -> usize {
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    match requested {
+        Some(n) if n > cpus * MAX_JOBS_PER_CPU => {
+            let max = cpus * MAX_JOBS_PER_CPU;
+            log::warn(&format!("--jobs {} is too many, using {}", n, max));
+            max
+        }
+        Some(n) => n,
+        None => cpus,
+    }
+}
+
+/// Non-interactive kill that prints a single JSON summary to stdout
+fn run_kill_json(path: &Path, args: &KillArgs) -> i32 {
+    log::set_machine(true);
+
+    if !args.yes && !args.dry_run {
+        log::error("--format json requires --yes or --dry-run");
+        return EXIT_ERROR;
+    }
+
+    let opts = kill_options(args)
+        .skip_tracked(skip_tracked(args))
+        // Keep the file list in a stable order
+        .ordered(true);
+    let notifier = args.notify.then_some(report::Notifier);
+    let mut observer = (report::JsonSummary::new(args.dry_run).stats(args.stats), notifier);
+    let result = Scanner::new(path)
+        .recursive(args.recursive)
+        .excludes(&args.exclude)
+        .filter(args.filter.to_filter())
+        .traversal(args.traversal.to_traversal())
+        .macosx(args.macosx)
+        .kill_with(&opts, &mut observer);
+
+    if !args.dry_run && result.deleted > 0 {
+        cache::invalidate(path, args.recursive);
+    }
+
+    result.exit_code(args.dry_run)
+}
+
+fn run_watch(mut args: WatchArgs, config: Option<&Path>) -> i32 {
+    let ndjson = args.format == WatchFormat::Ndjson;
+    log::set_machine(ndjson);
+
+    let mut resolved = Vec::new();
+    for p in &args.paths {
+        let Some(path) = resolve_dir(p) else { return EXIT_ERROR };
+        resolved.push(path);
+    }
+    let roots = watcher::distinct_roots(&resolved);
+    for p in resolved.iter().filter(|p| !roots.contains(p)) {
+        log::info(&format!("Already covered by another root: {}", p.display()));
+    }
+
+    // A `.dsk.toml` is looked up from the first root
+    for file in config::ignored_local_files(&roots) {
+        log::warn(&format!(
+            "Ignoring {}: watch options come from the first root's config",
+            log::shorten_path(&file)
+        ));
+    }
+    let Some(cfg) = configure(config, &roots[0], args.options.flags()) else { return EXIT_ERROR };
+    args.options.apply(&cfg);
+
+    let mut poll_paths = Vec::new();
+    for p in &args.options.poll_path {
+        let Some(path) = resolve_dir(p) else { return EXIT_ERROR };
+        poll_paths.push(path);
+    }
+
+    let opts = watcher::WatchOptions {
+        excludes: args.options.exclude.clone(),
+        notify: args.options.notify,
+        force: args.options.force,
+        ndjson,
+        debounce: Duration::from_millis(args.options.debounce.unwrap_or(DEBOUNCE_MS)),
+        sweep: args.options.sweep.filter(|&s| s > 0).map(Duration::from_secs),
+        poll: args.options.poll.map(Duration::from_secs),
+        poll_paths,
+        auto_poll: !args.options.no_auto_poll,
+        full_scan: args.options.full_scan,
+        socket: Some(socket_path(args.options.socket.as_deref())),
+        pauses: pause::load(&pause::file(), SystemTime::now()),
+        protect: protect::Protector::new(&args.options.keep),
+        filter: args.options.filter.to_filter(),
+        traversal: args.options.traversal.to_traversal(),
+    };
+
+    for p in opts.poll_paths.iter().filter(|p| !roots.contains(p)) {
+        log::warn(&format!("--poll-path is not a watched root: {}", p.display()));
     }
 
-    if let Err(e) = watcher::run(
-        &[path.as_path()],
-        args.options.exclude.clone(),
-        args.options.notify,
-        args.options.force,
-    ) {
+    let roots: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+    if let Err(e) = watcher::run(&roots, opts) {
         log::error(&e);
-        std::process::exit(1);
+        return EXIT_ERROR;
+    }
+    EXIT_OK
+}
+
+fn run_check(mut args: CheckArgs, config: Option<&Path>) -> i32 {
+    let Some(path) = resolve_dir(&args.path) else { return EXIT_ERROR };
+    let Some(cfg) = configure(config, &path, args.flags()) else { return EXIT_ERROR };
+    args.apply(&cfg);
+
+    let mut files = Scanner::new(&path)
+        .recursive(args.recursive)
+        .excludes(&args.exclude)
+        .traversal(args.traversal.to_traversal())
+        .scan();
+
+    if args.tracked_only {
+        if !git::is_available() {
+            log::error("--tracked-only requires git");
+            return EXIT_ERROR;
+        }
+        files = git::check_files(&files).tracked;
+    }
+
+    let base = env::current_dir().unwrap_or_else(|_| path.clone());
+    print!("{}", check::render(args.format, &files, &base));
+
+    if files.is_empty() { EXIT_OK } else { EXIT_FOUND }
+}
+
+/// Control socket to use: the given one or the default
+fn socket_path(socket: Option<&Path>) -> PathBuf {
+    let path = socket.map_or(CTL_SOCKET.into(), |p| p.to_string_lossy());
+    PathBuf::from(shellexpand::tilde(&path).as_ref())
+}
+
+fn run_ctl(socket: Option<&Path>, action: CtlAction) -> i32 {
+    let request = match action {
+        CtlAction::Stats => ctl::Request::Stats,
+        CtlAction::Pause => ctl::Request::Pause,
+        CtlAction::Resume => ctl::Request::Resume,
+        CtlAction::Rescan { path } => {
+            let Some(dir) = resolve_dir(&path) else { return EXIT_ERROR };
+            ctl::Request::Rescan(dir)
+        }
+        CtlAction::AddRoot { path } => {
+            let Some(dir) = resolve_dir(&path) else { return EXIT_ERROR };
+            ctl::Request::AddRoot(dir)
+        }
+        CtlAction::RemoveRoot { path } => {
+            // The root may be gone already, so don't insist on a directory
+            let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+            ctl::Request::RemoveRoot(path.canonicalize().unwrap_or(path))
+        }
+    };
+
+    let is_stats = request == ctl::Request::Stats;
+    match ctl::request(&socket_path(socket), &request) {
+        Ok(body) if is_stats => {
+            println!("{}", body);
+            EXIT_OK
+        }
+        Ok(body) => {
+            log::ok(&body);
+            EXIT_OK
+        }
+        Err(e) => {
+            log::error(&e);
+            EXIT_ERROR
+        }
+    }
+}
+
+fn run_pause(path: &Path, duration: Duration, socket: Option<&Path>) -> i32 {
+    let Some(path) = resolve_dir(path) else { return EXIT_ERROR };
+
+    let file = pause::file();
+    let now = SystemTime::now();
+    let mut pauses = pause::load(&file, now);
+    let Some(until) = now.checked_add(duration) else {
+        log::error(&format!("Pause too long: {}", pause::format_duration(duration)));
+        return EXIT_ERROR;
+    };
+    pause::add(&mut pauses, pause::Pause { path: path.clone(), until });
+    if let Err(e) = pause::save(&file, &pauses) {
+        log::error(&format!("Cannot save pause: {}", e));
+        return EXIT_ERROR;
+    }
+
+    let request = ctl::Request::PausePath(path.clone(), duration);
+    if ctl::request(&socket_path(socket), &request).is_err() {
+        log::info("No running watcher reached; the pause applies when it starts");
+    }
+    log::ok(&format!(
+        "Paused {} for {}",
+        log::shorten_path(&path),
+        pause::format_duration(duration)
+    ));
+    EXIT_OK
+}
+
+fn run_resume(path: &Path, socket: Option<&Path>) -> i32 {
+    let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+    let path = path.canonicalize().unwrap_or(path);
+
+    let file = pause::file();
+    let mut pauses = pause::load(&file, SystemTime::now());
+    if !pause::remove(&mut pauses, &path) {
+        log::error(&format!("Not paused: {}", path.display()));
+        return EXIT_ERROR;
+    }
+    if let Err(e) = pause::save(&file, &pauses) {
+        log::error(&format!("Cannot save pauses: {}", e));
+        return EXIT_ERROR;
+    }
+
+    // A watcher that is not running picks the change up when it starts
+    let _ = ctl::request(&socket_path(socket), &ctl::Request::ResumePath(path.clone()));
+    log::ok(&format!("Resumed {}", log::shorten_path(&path)));
+    EXIT_OK
+}
+
+fn run_archive(action: ArchiveAction) -> i32 {
+    let ArchiveAction::Clean { files, check, quiet } = action;
+    let mut found = 0;
+    let mut failed = false;
+
+    for file in &files {
+        let file = PathBuf::from(shellexpand::tilde(&file.to_string_lossy()).as_ref());
+        let result = if check { archive::check(&file) } else { archive::clean(&file) };
+        let entries = match result {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::error(&e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let name = log::shorten_path(&file);
+        if !quiet {
+            for entry in &entries {
+                log::detail(&format!("{}: {}", name, entry));
+            }
+        }
+        if entries.is_empty() {
+            log::info(&format!("No metadata entries in {}", name));
+        } else if check {
+            log::info(&format!("Found {} metadata entries in {}", entries.len(), name));
+        } else {
+            log::ok(&format!("Removed {} metadata entries from {}", entries.len(), name));
+        }
+        found += entries.len();
+    }
+
+    if failed {
+        EXIT_ERROR
+    } else if check && found > 0 {
+        EXIT_FOUND
+    } else {
+        EXIT_OK
     }
 }
 
-fn run_service(action: ServiceAction) {
+fn run_config(action: ConfigAction, config: Option<&Path>) -> i32 {
+    let ConfigAction::Show { path, effective } = action;
+    let Some(dir) = resolve_dir(&path) else { return EXIT_ERROR };
+    let sources = match config::sources(config, &dir, config::Layer::default()) {
+        Ok(sources) => sources,
+        Err(e) => {
+            log::error(&format!("Invalid configuration: {}", e));
+            return EXIT_ERROR;
+        }
+    };
+
+    if effective {
+        print!("{}", config::describe(&sources));
+        return EXIT_OK;
+    }
+
+    let (user, _) = config::user_file(config);
+    println!("# User config: {}", log::shorten_path(&user));
+    match config::local_file(&dir) {
+        Some(local) => println!("# Directory config: {}", log::shorten_path(&local)),
+        None => println!("# Directory config: none ({} not found)", config::LOCAL_FILE),
+    }
+    for source in &sources {
+        println!("\n# {}", source.name);
+        print!("{}", source.layer.render());
+    }
+    EXIT_OK
+}
+
+fn run_service(action: ServiceAction, config: Option<&Path>) -> i32 {
     let result = match action {
-        ServiceAction::Install { paths, watch_args } => service::install(&paths, &watch_args),
+        ServiceAction::Install { paths, watch_args } => {
+            service::install(&paths, &watch_args, config)
+        }
         ServiceAction::Uninstall => service::uninstall(),
         ServiceAction::Start => service::start(),
         ServiceAction::Stop => service::stop(),
         ServiceAction::Status => service::status(),
     };
-    if let Err(e) = result {
-        log::error(&e);
-        std::process::exit(1);
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            log::error(&e);
+            EXIT_ERROR
+        }
     }
 }
 
-fn scan_and_cache(dir: &Path, recursive: bool, excludes: &[String]) -> Vec<std::path::PathBuf> {
-    let mut files = Vec::new();
-    killer::scan_streaming(dir, recursive, excludes, |p| {
-        log::found(p);
-        files.push(p.to_path_buf());
-    });
-    cache::save(dir, recursive, &files);
-    files
+/// Whether the scan cache applies: it only holds plain `.DS_Store` results
+/// of a default walk
+fn cacheable(args: &KillArgs) -> bool {
+    args.filter.to_filter().is_empty()
+        && !args.macosx
+        && args.traversal.to_traversal() == killer::Traversal::default()
 }
 
-fn confirm(msg: &str) -> bool {
+fn scan_and_cache(dir: &Path, args: &KillArgs) -> (Vec<PathBuf>, Stats) {
+    let (files, stats) = Scanner::new(dir)
+        .recursive(args.recursive)
+        .excludes(&args.exclude)
+        .filter(args.filter.to_filter())
+        .traversal(args.traversal.to_traversal())
+        .macosx(args.macosx)
+        .scan_stats(&mut report::Printer::listing());
+    if cacheable(args) {
+        cache::save(dir, args.recursive, &files);
+    }
+    (files, stats)
+}
+
+/// Ask a yes/no question on stdin, or on the terminal itself with `tty`
+fn confirm(msg: &str, tty: bool) -> bool {
     print!("{} [y/N] ", msg);
     io::stdout().flush().ok();
 
     let mut input = String::new();
-    if io::stdin().read_line(&mut input).is_err() {
+    let read = if tty {
+        fs::File::open("/dev/tty").and_then(|t| io::BufReader::new(t).read_line(&mut input))
+    } else {
+        io::stdin().read_line(&mut input)
+    };
+    if read.is_err() {
         return false;
     }
 
@@ -0,0 +1,166 @@
+//! Timed watcher pauses for a subtree, persisted across restarts
+//!
+//! `dsk pause` records each pause in a small state file and tells a running
+//! watcher over the control socket. A watcher starting later reads the file,
+//! so a pause outlives restarts until it expires or is lifted.
+
+use crate::consts::STATE_DIR;
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Leave targets under `path` alone until `until`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pause {
+    pub path: PathBuf,
+    pub until: SystemTime,
+}
+
+impl Pause {
+    /// Time left at `now`
+    pub fn remaining(&self, now: SystemTime) -> Duration {
+        self.until.duration_since(now).unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Longest accepted duration: ten years
+pub const MAX_DURATION: Duration = Duration::from_secs(10 * 365 * 86400);
+
+/// Where active pauses are kept
+pub fn file() -> PathBuf {
+    PathBuf::from(shellexpand::tilde(STATE_DIR).as_ref()).join("pauses")
+}
+
+/// Parse `90s`, `30m`, `2h`, `1d` or plain seconds, up to [`MAX_DURATION`]
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num.parse().map_err(|_| format!("invalid duration: {}", s))?;
+    let unit_secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("invalid duration unit in {} (use s, m, h or d)", s)),
+    };
+    let secs = n
+        .checked_mul(unit_secs)
+        .filter(|&secs| secs <= MAX_DURATION.as_secs())
+        .ok_or_else(|| format!("duration too long: {} (at most 10 years)", s))?;
+    if secs == 0 {
+        return Err("duration must be positive".into());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Short human form of a duration, e.g. `1h 5m` or `45s`
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// Pauses in `file` still active at `now`
+pub fn load(file: &Path, now: SystemTime) -> Vec<Pause> {
+    let Ok(content) = fs::read_to_string(file) else { return Vec::new() };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (secs, path) = line.split_once(' ')?;
+            let until = UNIX_EPOCH.checked_add(Duration::from_secs(secs.parse().ok()?))?;
+            Some(Pause { path: PathBuf::from(path), until })
+        })
+        .filter(|p| p.until > now)
+        .collect()
+}
+
+/// Write `pauses` to `file`, replacing its content
+pub fn save(file: &Path, pauses: &[Pause]) -> Result<(), String> {
+    let mut out = String::new();
+    for p in pauses {
+        let secs = p.until.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let _ = writeln!(out, "{} {}", secs, p.path.display());
+    }
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // Write then rename, so a crash never leaves a truncated file
+    let tmp = file.with_extension("tmp");
+    fs::write(&tmp, out).map_err(|e| e.to_string())?;
+    fs::rename(&tmp, file).map_err(|e| e.to_string())
+}
+
+/// Add a pause, replacing any existing pause of the same path
+pub fn add(pauses: &mut Vec<Pause>, pause: Pause) {
+    pauses.retain(|p| p.path != pause.path);
+    pauses.push(pause);
+}
+
+/// Remove the pause of `path`; false if there was none
+pub fn remove(pauses: &mut Vec<Pause>, path: &Path) -> bool {
+    let before = pauses.len();
+    pauses.retain(|p| p.path != path);
+    pauses.len() != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert!(parse_duration("m").is_err());
+        assert_eq!(parse_duration("3650d"), Ok(MAX_DURATION));
+        assert!(parse_duration("3651d").is_err());
+        assert!(parse_duration("999999999999999d").is_err());
+        assert!(parse_duration("18446744073709551615s").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(1800)), "30m");
+        assert_eq!(format_duration(Duration::from_secs(1805)), "30m 5s");
+        assert_eq!(format_duration(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 5m");
+    }
+
+    #[test]
+    fn test_save_load_drops_expired() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("state/pauses");
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let mut pauses = Vec::new();
+        add(&mut pauses, Pause { path: "/a b".into(), until: now + Duration::from_secs(60) });
+        add(&mut pauses, Pause { path: "/old".into(), until: now - Duration::from_secs(1) });
+        add(&mut pauses, Pause { path: "/a b".into(), until: now + Duration::from_secs(600) });
+        save(&file, &pauses).unwrap();
+
+        let loaded = load(&file, now);
+        assert_eq!(loaded, vec![Pause { path: "/a b".into(), until: now + Duration::from_secs(600) }]);
+        assert_eq!(loaded[0].remaining(now), Duration::from_secs(600));
+
+        let mut loaded = loaded;
+        assert!(remove(&mut loaded, Path::new("/a b")));
+        assert!(!remove(&mut loaded, Path::new("/a b")));
+        assert!(load(&dir.path().join("missing"), now).is_empty());
+    }
+}
@@ -0,0 +1,578 @@
+//! Polling fallback for file systems without usable change events
+//!
+//! inotify never fires for changes made by other clients of NFS/SMB/sshfs
+//! mounts. For such roots the watcher instead polls: every directory's mtime
+//! is recorded, and only directories whose mtime moved are re-read. Results
+//! are reported as ordinary [`FsEvent`]s so the engine treats them the same
+//! way as kernel events.
+//!
+//! The same directory state doubles as the watch checkpoint: it is saved on
+//! shutdown, and on the next start only directories whose mtime moved since
+//! are re-read instead of scanning every root from scratch.
+
+use crate::{
+    consts::{PSEUDO_FS, STATE_DIR, TARGET_FILE},
+    engine::FsEvent,
+    killer::{self, Traversal},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// File system types whose remote changes don't produce local events
+const POLLED_FS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "afpfs", "9p", "davfs", "ceph", "glusterfs",
+];
+
+/// First line of a checkpoint file; bump when the format changes
+const CHECKPOINT_HEADER: &str = "dsk-checkpoint 1";
+
+/// Directory mtimes closer than this to the time they were read may hide
+/// further changes (coarse timestamp resolution), so they are re-read
+const MTIME_SLACK: Duration = Duration::from_secs(2);
+
+/// Undo the octal escapes (`\040` for space etc.) used in mountinfo
+fn unescape(field: &str) -> String {
+    let mut out = Vec::with_capacity(field.len());
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u8::from_str_radix(d, 8).ok());
+        if bytes[i] == b'\\'
+            && let Some(b) = octal
+        {
+            out.push(b);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Find the file system type of the mount containing `path`
+///
+/// `mountinfo` is the content of `/proc/self/mountinfo`; the mount with the
+/// longest mount point that is a prefix of `path` wins.
+pub fn fs_type_in(mountinfo: &str, path: &Path) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (left, right) = line.split_once(" - ")?;
+            let mount_point = unescape(left.split(' ').nth(4)?);
+            let fs_type = right.split(' ').next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// File system type of `path`, where the platform exposes mountinfo
+pub fn fs_type(path: &Path) -> Option<String> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    fs_type_in(&mountinfo, path)
+}
+
+/// Whether kernel events can't be trusted on this file system type
+pub fn needs_polling(fs_type: &str) -> bool {
+    fs_type.starts_with("fuse") || POLLED_FS.contains(&fs_type)
+}
+
+/// Last observed state of a directory
+struct DirState {
+    mtime: SystemTime,
+    /// mtime was far enough in the past when read to be trusted
+    settled: bool,
+    /// Device and inode, tracked only when following symlinks
+    id: Option<(u64, u64)>,
+}
+
+/// Incremental directory-mtime scanner for one root
+pub struct Poller {
+    root: PathBuf,
+    excludes: Vec<String>,
+    /// Depth, file system and symlink limits, the same as for scans
+    traversal: Traversal,
+    /// Device of the root, when the walk must stay on it
+    root_dev: Option<u64>,
+    /// Directories already recorded, when following symlinks
+    seen: HashSet<(u64, u64)>,
+    dirs: HashMap<PathBuf, DirState>,
+}
+
+impl Poller {
+    fn empty(root: &Path, excludes: &[String], traversal: &Traversal) -> Self {
+        let root_dev = fs::metadata(root)
+            .ok()
+            .filter(|_| traversal.one_file_system)
+            .map(|m| m.dev());
+        Self {
+            root: root.to_path_buf(),
+            excludes: excludes.to_vec(),
+            traversal: traversal.clone(),
+            root_dev,
+            seen: HashSet::new(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Create a poller and record the current state of the whole tree
+    ///
+    /// The targets seen on the way are returned, so a cold start needs no
+    /// separate scan.
+    pub fn new(root: &Path, excludes: &[String], traversal: &Traversal) -> (Self, Vec<PathBuf>) {
+        let mut poller = Self::empty(root, excludes, traversal);
+        let mut found = Vec::new();
+        poller.register(root, &mut found);
+        (poller, found)
+    }
+
+    /// Whether targets directly inside `dir` are within the depth limit
+    fn within(&self, dir: &Path) -> bool {
+        let depth = dir.strip_prefix(&self.root).map_or(0, |rel| rel.components().count());
+        self.traversal.max_depth.is_none_or(|max| depth < max)
+    }
+
+    /// Whether the walk goes into the entry at `path`; file system and
+    /// symlink loop checks happen when it is registered
+    fn enters(&self, path: &Path, kind: fs::FileType) -> bool {
+        let is_dir = kind.is_dir()
+            || (self.traversal.follow_symlinks && kind.is_symlink() && path.is_dir());
+        is_dir
+            && !killer::is_excluded(path, &self.excludes)
+            && self.within(path)
+            && !PSEUDO_FS.iter().any(|p| path == Path::new(p))
+    }
+
+    /// Record the state of `dir` and all its subdirectories, collecting the
+    /// targets in them into `found`
+    fn register(&mut self, dir: &Path, found: &mut Vec<PathBuf>) {
+        let Ok(meta) = fs::metadata(dir) else { return };
+        let Ok(mtime) = meta.modified() else { return };
+        if self.root_dev.is_some_and(|dev| dev != meta.dev()) {
+            return;
+        }
+        let id = self.traversal.follow_symlinks.then(|| (meta.dev(), meta.ino()));
+        if id.is_some_and(|id| !self.seen.insert(id)) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else { return };
+
+        self.dirs.insert(dir.to_path_buf(), DirState { mtime, settled: is_settled(mtime), id });
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else { continue };
+            if self.enters(&path, kind) {
+                self.register(&path, found);
+            } else if !kind.is_dir() && entry.file_name() == TARGET_FILE {
+                found.push(path);
+            }
+        }
+    }
+
+    /// Forget `dir` and everything recorded below it
+    fn forget(&mut self, dir: &Path) {
+        let seen = &mut self.seen;
+        self.dirs.retain(|p, state| {
+            let keep = !p.starts_with(dir);
+            if !keep && let Some(id) = state.id {
+                seen.remove(&id);
+            }
+            keep
+        });
+    }
+
+    /// Check every known directory and report what changed since last poll
+    pub fn poll(&mut self) -> Vec<FsEvent> {
+        let mut events = Vec::new();
+        let mut known: Vec<PathBuf> = self.dirs.keys().cloned().collect();
+        // Parents first, so removed subtrees are dropped before visiting them
+        known.sort();
+
+        for dir in known {
+            let Some(state) = self.dirs.get(&dir) else { continue };
+
+            let mtime = match fs::metadata(&dir).and_then(|m| m.modified()) {
+                Ok(t) => t,
+                Err(_) => {
+                    // Keep the root so it is picked up again if it returns
+                    if dir != self.root {
+                        self.forget(&dir);
+                    }
+                    continue;
+                }
+            };
+            if mtime == state.mtime && state.settled {
+                continue;
+            }
+            self.refresh(&dir, mtime, &mut events);
+        }
+
+        events
+    }
+
+    /// Re-read a directory whose mtime changed
+    ///
+    /// A target present in a changed directory is reported even if it was
+    /// there before: it may have been deleted and recreated in between, and
+    /// the engine de-duplicates anyway.
+    fn refresh(&mut self, dir: &Path, mtime: SystemTime, events: &mut Vec<FsEvent>) {
+        let Ok(entries) = fs::read_dir(dir) else { return };
+
+        let mut new_dirs = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else { continue };
+            if self.enters(&path, kind) {
+                if !self.dirs.contains_key(&path) {
+                    new_dirs.push(path);
+                }
+            } else if !kind.is_dir() && entry.file_name() == TARGET_FILE {
+                events.push(FsEvent::Created(path));
+            }
+        }
+
+        let id = self.dirs.get(dir).and_then(|state| state.id);
+        self.dirs.insert(dir.to_path_buf(), DirState { mtime, settled: is_settled(mtime), id });
+
+        for sub in new_dirs {
+            // The engine scans the new subtree; we only need its state
+            self.register(&sub, &mut Vec::new());
+            // Left out: another file system, or a directory already seen
+            if self.dirs.contains_key(&sub) {
+                events.push(FsEvent::DirCreated(sub));
+            }
+        }
+    }
+}
+
+impl Poller {
+    /// Bring the recorded state up to date and write it to `file`
+    ///
+    /// Directories that still hold a target (git-tracked, or created during
+    /// shutdown) are saved as unsettled so the next start re-reads them.
+    pub fn save(&mut self, file: &Path) -> Result<(), String> {
+        for event in self.poll() {
+            if let FsEvent::Created(path) = event
+                && let Some(state) = path.parent().and_then(|d| self.dirs.get_mut(d))
+            {
+                state.settled = false;
+            }
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", CHECKPOINT_HEADER);
+        let _ = writeln!(out, "root {}", self.root.display());
+        for ex in &self.excludes {
+            let _ = writeln!(out, "exclude {}", ex);
+        }
+        if let Some(max) = self.traversal.max_depth {
+            let _ = writeln!(out, "max_depth {}", max);
+        }
+        if self.traversal.one_file_system {
+            let _ = writeln!(out, "one_file_system");
+        }
+        if self.traversal.follow_symlinks {
+            let _ = writeln!(out, "follow_symlinks");
+        }
+        for (dir, state) in &self.dirs {
+            let Ok(since) = state.mtime.duration_since(UNIX_EPOCH) else { continue };
+            let path = dir.to_string_lossy();
+            // Unrepresentable entries are left out and show up as new next time
+            if path.contains('\n') {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "d {} {} {} {}",
+                since.as_secs(),
+                since.subsec_nanos(),
+                state.settled as u8,
+                path
+            );
+        }
+
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // Write then rename, so a crash never leaves a truncated checkpoint
+        let tmp = file.with_extension("tmp");
+        fs::write(&tmp, out).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, file).map_err(|e| e.to_string())
+    }
+
+    /// Restore state saved by [`Poller::save`]
+    ///
+    /// Returns `None` when the file is missing, from another format version,
+    /// or was written for a different root, exclude list or traversal.
+    pub fn load(
+        file: &Path,
+        root: &Path,
+        excludes: &[String],
+        traversal: &Traversal,
+    ) -> Option<Self> {
+        let content = fs::read_to_string(file).ok()?;
+        let mut lines = content.lines();
+        if lines.next()? != CHECKPOINT_HEADER {
+            return None;
+        }
+        if Path::new(lines.next()?.strip_prefix("root ")?) != root {
+            return None;
+        }
+
+        let mut saved_excludes = Vec::new();
+        let mut saved = Traversal::default();
+        let mut dirs = HashMap::new();
+        for line in lines {
+            if let Some(ex) = line.strip_prefix("exclude ") {
+                saved_excludes.push(ex.to_string());
+                continue;
+            }
+            if let Some(max) = line.strip_prefix("max_depth ") {
+                saved.max_depth = Some(max.parse().ok()?);
+                continue;
+            }
+            match line {
+                "one_file_system" => saved.one_file_system = true,
+                "follow_symlinks" => saved.follow_symlinks = true,
+                _ => {
+                    let (path, state) = parse_dir(line)?;
+                    dirs.insert(path, state);
+                }
+            }
+        }
+
+        let same = saved.max_depth == traversal.max_depth
+            && saved.one_file_system == traversal.one_file_system
+            && saved.follow_symlinks == traversal.follow_symlinks;
+        if saved_excludes != excludes || !same || !dirs.contains_key(root) {
+            return None;
+        }
+        let mut poller = Self::empty(root, excludes, traversal);
+        if traversal.follow_symlinks {
+            // Directories that are gone now are forgotten by the next poll
+            for (dir, state) in &mut dirs {
+                if let Ok(meta) = fs::metadata(dir) {
+                    state.id = Some((meta.dev(), meta.ino()));
+                    poller.seen.insert((meta.dev(), meta.ino()));
+                }
+            }
+        }
+        poller.dirs = dirs;
+        Some(poller)
+    }
+}
+
+/// Parse a `d secs nanos settled path` checkpoint line
+fn parse_dir(line: &str) -> Option<(PathBuf, DirState)> {
+    let mut fields = line.strip_prefix("d ")?.splitn(4, ' ');
+    let secs: u64 = fields.next()?.parse().ok()?;
+    let nanos: u32 = fields.next()?.parse().ok()?;
+    let settled = fields.next()? == "1";
+    let path = PathBuf::from(fields.next()?);
+    let mtime = UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+    Some((path, DirState { mtime, settled, id: None }))
+}
+
+/// Where the checkpoint for `root` is kept
+///
+/// Named by a hash of the path (FNV-1a), so no two roots share a file; a
+/// collision only costs a full scan, as [`Poller::load`] checks the root.
+pub fn checkpoint_path(root: &Path) -> PathBuf {
+    let hash = root.as_os_str().as_bytes().iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    PathBuf::from(shellexpand::tilde(STATE_DIR).as_ref()).join(format!("checkpoint-{:016x}", hash))
+}
+
+/// Poll on a background thread every `interval`
+///
+/// Events are passed to `send`; the thread exits once `send` returns false
+/// (the receiving side has gone away).
+pub fn spawn<F>(poller: Arc<Mutex<Poller>>, interval: Duration, send: F)
+where
+    F: Fn(FsEvent) -> bool + Send + 'static,
+{
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            let Ok(events) = poller.lock().map(|mut p| p.poll()) else { return };
+            for event in events {
+                if !send(event) {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Whether an mtime is old enough that no same-timestamp change can follow
+fn is_settled(mtime: SystemTime) -> bool {
+    SystemTime::now()
+        .duration_since(mtime)
+        .is_ok_and(|age| age >= MTIME_SLACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+35 22 0:31 / /mnt/nas rw,relatime shared:20 - nfs4 nas:/export rw,vers=4.2
+36 22 0:32 / /home/me/remote\\040box rw,nosuid - fuse.sshfs me@box:/ rw
+37 35 0:33 / /mnt/nas/local rw - ext4 /dev/sdb1 rw
+";
+
+    #[test]
+    fn test_fs_type_in() {
+        let t = |p: &str| fs_type_in(MOUNTINFO, Path::new(p));
+        assert_eq!(t("/home/me").as_deref(), Some("ext4"));
+        assert_eq!(t("/mnt/nas/photos").as_deref(), Some("nfs4"));
+        assert_eq!(t("/mnt/nas/local/x").as_deref(), Some("ext4"));
+        assert_eq!(t("/home/me/remote box/docs").as_deref(), Some("fuse.sshfs"));
+        assert_eq!(t("/mnt/nasty").as_deref(), Some("ext4"));
+    }
+
+    #[test]
+    fn test_needs_polling() {
+        assert!(needs_polling("nfs4"));
+        assert!(needs_polling("cifs"));
+        assert!(needs_polling("fuse.sshfs"));
+        assert!(!needs_polling("ext4"));
+        assert!(!needs_polling("apfs"));
+    }
+
+    #[test]
+    fn test_poller_reports_changes() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+        fs::create_dir(&sub).unwrap();
+        File::create(sub.join(TARGET_FILE)).unwrap();
+
+        let (mut poller, found) = Poller::new(root, &["skip".to_string()], &Traversal::default());
+        // The walk that records the tree also finds what is already there
+        assert_eq!(found, vec![sub.join(TARGET_FILE)]);
+
+        File::create(root.join(TARGET_FILE)).unwrap();
+        fs::create_dir_all(root.join("new/deep")).unwrap();
+        fs::create_dir(root.join("skip")).unwrap();
+
+        let events = poller.poll();
+        assert!(events.contains(&FsEvent::Created(root.join(TARGET_FILE))));
+        assert!(events.contains(&FsEvent::DirCreated(root.join("new"))));
+        assert!(!events.contains(&FsEvent::DirCreated(root.join("skip"))));
+
+        // The new subtree is tracked from now on
+        File::create(root.join("new/deep").join(TARGET_FILE)).unwrap();
+        let events = poller.poll();
+        assert!(events.contains(&FsEvent::Created(root.join("new/deep").join(TARGET_FILE))));
+
+        // Removed directories are forgotten
+        fs::remove_dir_all(root.join("new")).unwrap();
+        poller.poll();
+        assert!(!poller.dirs.contains_key(&root.join("new/deep")));
+        assert!(poller.dirs.contains_key(root));
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        let file = dir.path().join("state/checkpoint");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir(root.join("c")).unwrap();
+        let excludes = vec!["skip".to_string()];
+
+        let none = Traversal::default();
+        let (mut poller, _) = Poller::new(&root, &excludes, &none);
+        // A target left behind keeps its directory unsettled
+        File::create(root.join("c").join(TARGET_FILE)).unwrap();
+        poller.save(&file).unwrap();
+
+        let mut resumed = Poller::load(&file, &root, &excludes, &none).unwrap();
+        assert_eq!(resumed.dirs.len(), 4);
+        assert!(!resumed.dirs[&root.join("c")].settled);
+
+        // Only what changed since the checkpoint is reported
+        fs::create_dir(root.join("a/b/new")).unwrap();
+        let events = resumed.poll();
+        assert!(events.contains(&FsEvent::DirCreated(root.join("a/b/new"))));
+        assert!(events.contains(&FsEvent::Created(root.join("c").join(TARGET_FILE))));
+
+        // Wrong root, different excludes or traversal, or a damaged file fall
+        // back to a full scan
+        let follow = Traversal { follow_symlinks: true, ..Traversal::default() };
+        assert!(Poller::load(&file, &root.join("a"), &excludes, &none).is_none());
+        assert!(Poller::load(&file, &root, &[], &none).is_none());
+        assert!(Poller::load(&file, &root, &excludes, &follow).is_none());
+        fs::write(&file, "dsk-checkpoint 1\nroot /x\ngarbage").unwrap();
+        assert!(Poller::load(&file, &root, &excludes, &none).is_none());
+    }
+
+    #[test]
+    fn test_poller_max_depth() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        let file = dir.path().join("checkpoint");
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+
+        // Targets in a/b would be at depth 3, so a/b is never read
+        let depth = Traversal { max_depth: Some(2), ..Traversal::default() };
+        let (mut poller, _) = Poller::new(&root, &[], &depth);
+        assert!(poller.dirs.contains_key(&root.join("a")));
+        assert!(!poller.dirs.contains_key(&root.join("a/b")));
+
+        fs::create_dir(root.join("a/new")).unwrap();
+        File::create(root.join("a").join(TARGET_FILE)).unwrap();
+        let events = poller.poll();
+        assert!(events.contains(&FsEvent::Created(root.join("a").join(TARGET_FILE))));
+        assert!(!events.contains(&FsEvent::DirCreated(root.join("a/new"))));
+
+        poller.save(&file).unwrap();
+        assert!(Poller::load(&file, &root, &[], &depth).is_some());
+        assert!(Poller::load(&file, &root, &[], &Traversal::default()).is_none());
+    }
+
+    #[test]
+    fn test_poller_symlinks() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("real")).unwrap();
+        File::create(root.join("real").join(TARGET_FILE)).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root, root.join("real/loop")).unwrap();
+
+        let (poller, found) = Poller::new(root, &[], &Traversal::default());
+        assert!(!poller.dirs.contains_key(&root.join("link")));
+        assert_eq!(found.len(), 1);
+
+        // Each directory is recorded once, however many links lead to it
+        let follow = Traversal { follow_symlinks: true, ..Traversal::default() };
+        let (poller, found) = Poller::new(root, &[], &follow);
+        assert_eq!(poller.dirs.len(), 2);
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn test_checkpoint_path() {
+        assert_ne!(checkpoint_path(Path::new("/a_b")), checkpoint_path(Path::new("/a/b")));
+        assert_eq!(checkpoint_path(Path::new("/a/b")), checkpoint_path(Path::new("/a/b")));
+    }
+}
@@ -0,0 +1,237 @@
+//! Protection rules for intentional `.DS_Store` files
+//!
+//! Some `.DS_Store` files carry layout that somebody wants: the window of a
+//! DMG (background picture and icon positions) lives in the `.DS_Store` next
+//! to its `.background` folder, and app bundles may ship one as a resource.
+//! Protected files are still found, but reported as "protected" instead of
+//! being deleted.
+
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::{Arc, OnceLock},
+};
+
+/// Where macOS mounts disk images
+const VOLUMES: &str = "/Volumes";
+
+/// Why a target is left alone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// Next to a `.background` folder, i.e. a DMG window layout
+    DmgLayout,
+    /// Inside `*.app/Contents/Resources`
+    AppResources,
+    /// On a volume mounted from a disk image
+    DiskImage,
+    /// Matches a user keep rule
+    Keep(String),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DmgLayout => write!(f, "DMG layout"),
+            Self::AppResources => write!(f, "app bundle resource"),
+            Self::DiskImage => write!(f, "disk image volume"),
+            Self::Keep(rule) => write!(f, "keep rule {}", rule),
+        }
+    }
+}
+
+/// Built-in rules plus user keep rules
+#[derive(Debug, Clone, Default)]
+pub struct Protector {
+    /// Keep patterns with `~` expanded
+    keep: Vec<String>,
+    /// Mount points of attached disk images, looked up on first use and
+    /// shared between clones
+    images: Arc<OnceLock<Vec<PathBuf>>>,
+}
+
+impl Protector {
+    /// Built-in rules plus `keep` patterns; attached disk images are looked
+    /// up once, when the first target under `/Volumes` is checked
+    pub fn new(keep: &[String]) -> Self {
+        Self {
+            keep: keep.iter().map(|k| shellexpand::tilde(k).into_owned()).collect(),
+            images: Arc::default(),
+        }
+    }
+
+    /// Whether `target` is on an attached disk image
+    fn on_image(&self, target: &Path) -> bool {
+        target.starts_with(VOLUMES)
+            && self.images.get_or_init(disk_image_mounts).iter().any(|m| target.starts_with(m))
+    }
+
+    /// Why `target` must not be deleted, if it must not
+    pub fn check(&self, target: &Path) -> Option<Reason> {
+        if let Some(rule) = self.keep.iter().find(|k| keep_matches(k, target)) {
+            return Some(Reason::Keep(rule.clone()));
+        }
+        if in_app_resources(target) {
+            return Some(Reason::AppResources);
+        }
+        if self.on_image(target) {
+            return Some(Reason::DiskImage);
+        }
+        if target.parent().is_some_and(|d| d.join(".background").is_dir()) {
+            return Some(Reason::DmgLayout);
+        }
+        None
+    }
+}
+
+/// Whether a path lies inside some `*.app/Contents/Resources`
+fn in_app_resources(path: &Path) -> bool {
+    let names: Vec<&str> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => n.to_str(),
+            _ => None,
+        })
+        .collect();
+    names
+        .windows(3)
+        .any(|w| w[0].ends_with(".app") && w[1] == "Contents" && w[2] == "Resources")
+}
+
+/// Match a keep rule against a target
+///
+/// A rule containing `/` matches the target path or any directory above it
+/// (`~/Design/dmg`, `/Volumes/*/Layout`); a rule without `/` matches the
+/// name of any directory above the target (`*.dmgproj`).
+fn keep_matches(rule: &str, target: &Path) -> bool {
+    if rule.contains('/') {
+        let rule = rule.trim_end_matches('/');
+        target
+            .ancestors()
+            .any(|a| wildcard(rule, &a.to_string_lossy()))
+    } else {
+        target
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|c| match c {
+                Component::Normal(n) => n.to_str(),
+                _ => None,
+            })
+            .any(|name| wildcard(rule, name))
+    }
+}
+
+/// Glob-style match where `*` is any run of characters and `?` any one
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            pi += 1;
+            backtrack = Some((pi, ti));
+        } else if let Some((bp, bt)) = backtrack {
+            pi = bp;
+            ti = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Mount points listed by `hdiutil info` (macOS only; empty elsewhere)
+fn disk_image_mounts() -> Vec<PathBuf> {
+    Command::new("hdiutil")
+        .arg("info")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_hdiutil_info(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+/// Extract mount points from `hdiutil info` device lines
+/// (`/dev/disk4s1<TAB>Apple_HFS<TAB>/Volumes/Foo`)
+fn parse_hdiutil_info(out: &str) -> Vec<PathBuf> {
+    out.lines()
+        .filter(|l| l.starts_with("/dev/"))
+        .filter_map(|l| l.split('\t').nth(2))
+        .map(str::trim)
+        .filter(|m| m.starts_with('/'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn protector(keep: &[&str], images: &[&str]) -> Protector {
+        Protector {
+            keep: keep.iter().map(|k| k.to_string()).collect(),
+            images: Arc::new(images.iter().map(PathBuf::from).collect::<Vec<_>>().into()),
+        }
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert!(wildcard("*.dmgproj", "App.dmgproj"));
+        assert!(wildcard("Install?r", "Installer"));
+        assert!(wildcard("/Volumes/*/Layout", "/Volumes/My App/Layout"));
+        assert!(wildcard("a*b*c", "a-b-b-c"));
+        assert!(!wildcard("*.dmgproj", "App.dmgproj.bak"));
+        assert!(!wildcard("a*b", "a-c"));
+    }
+
+    #[test]
+    fn test_keep_rules() {
+        let p = protector(&["/work/dmg", "*.dmgproj"], &[]);
+        assert_eq!(
+            p.check(Path::new("/work/dmg/sub/.DS_Store")),
+            Some(Reason::Keep("/work/dmg".into()))
+        );
+        assert!(p.check(Path::new("/x/Foo.dmgproj/.DS_Store")).is_some());
+        assert!(p.check(Path::new("/work/dmg2/.DS_Store")).is_none());
+        assert!(p.check(Path::new("/x/.DS_Store")).is_none());
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let p = protector(&[], &["/Volumes/Installer"]);
+        assert_eq!(
+            p.check(Path::new("/Applications/X.app/Contents/Resources/.DS_Store")),
+            Some(Reason::AppResources)
+        );
+        assert!(p.check(Path::new("/Applications/X.app/Contents/.DS_Store")).is_none());
+        assert_eq!(
+            p.check(Path::new("/Volumes/Installer/.DS_Store")),
+            Some(Reason::DiskImage)
+        );
+
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".background")).unwrap();
+        assert_eq!(p.check(&dir.path().join(".DS_Store")), Some(Reason::DmgLayout));
+        assert!(p.check(&dir.path().join("sub/.DS_Store")).is_none());
+    }
+
+    #[test]
+    fn test_parse_hdiutil_info() {
+        let out = "framework       : 671\n\
+                   ================================================\n\
+                   image-path      : /Users/me/Foo.dmg\n\
+                   /dev/disk4\tGUID_partition_scheme\t\n\
+                   /dev/disk4s1\tApple_HFS\t/Volumes/Foo Installer\n";
+        assert_eq!(parse_hdiutil_info(out), vec![PathBuf::from("/Volumes/Foo Installer")]);
+    }
+}
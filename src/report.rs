@@ -0,0 +1,188 @@
+//! Built-in kill observers: terminal printer, JSON summary and notifications
+
+use crate::{
+    killer::{KillObserver, KillResult, Skip, Skipped, Stats, per_sec},
+    log,
+};
+use std::{path::Path, process::Command};
+
+/// Prints progress to the terminal
+pub struct Printer {
+    quiet: bool,
+    listing: bool,
+}
+
+impl Printer {
+    /// Print each deletion (or dry-run match) unless `quiet`
+    pub fn new(quiet: bool) -> Self {
+        Self { quiet, listing: false }
+    }
+
+    /// Print each target as it is found, for a listing before a prompt
+    pub fn listing() -> Self {
+        Self { quiet: false, listing: true }
+    }
+}
+
+impl KillObserver for Printer {
+    fn on_found(&mut self, path: &Path) {
+        if self.listing {
+            log::found(path);
+        }
+    }
+
+    fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+        match why {
+            // Oversized files are flagged even in quiet mode
+            Skipped::Filtered(Skip::Large(n)) => log::warn(&format!(
+                "Unusually large .DS_Store left in place ({} bytes): {}",
+                n,
+                path.display()
+            )),
+            // So are __MACOSX folders that turned out to hold real files
+            Skipped::Foreign(file) => log::warn(&format!(
+                "Left {} in place, it holds a non-AppleDouble file: {}",
+                path.display(),
+                file.display()
+            )),
+            Skipped::Protected(reason) if !self.quiet => log::keep(path, reason),
+            _ => {}
+        }
+    }
+
+    fn on_deleted(&mut self, path: &Path, dry_run: bool) {
+        if self.quiet || self.listing {
+            return;
+        }
+        if dry_run {
+            log::dry(path);
+        } else {
+            log::kill(path);
+        }
+    }
+}
+
+/// Prints the `kill --format json` summary when the run finishes
+pub struct JsonSummary {
+    dry_run: bool,
+    stats: bool,
+    walked: Stats,
+}
+
+impl JsonSummary {
+    pub fn new(dry_run: bool) -> Self {
+        Self { dry_run, stats: false, walked: Stats::default() }
+    }
+
+    /// Count a walk done before the kill (e.g. expanding a target list)
+    /// as part of the run
+    pub fn walked(mut self, walked: Stats) -> Self {
+        self.walked = walked;
+        self
+    }
+
+    /// Add the `--stats` breakdown under a `stats` key
+    pub fn stats(mut self, on: bool) -> Self {
+        self.stats = on;
+        self
+    }
+}
+
+impl KillObserver for JsonSummary {
+    fn on_finished(&mut self, result: &KillResult) {
+        let mut result = result.clone();
+        result.duration += self.walked.walk;
+        result.stats.add(&self.walked);
+
+        let mut summary = result.summary(self.dry_run);
+        if self.stats {
+            summary = summary.raw("stats", &result.stats_json());
+        }
+        println!("{}", summary.finish());
+    }
+}
+
+/// Sends a macOS notification when a run deleted something
+pub struct Notifier;
+
+impl KillObserver for Notifier {
+    fn on_finished(&mut self, result: &KillResult) {
+        if result.deleted > 0 {
+            notify(&result.to_string());
+        }
+    }
+}
+
+/// The `--stats` block: what was looked at, timings and throughput
+pub fn stats(result: &KillResult) -> String {
+    let s = &result.stats;
+    let mut out = format!(
+        "  Time: {:?} (walk {:?}, git {:?}, delete {:?})\n",
+        result.duration, s.walk, s.git, s.delete
+    );
+    let files = format!("{:.0} files/s", per_sec(result.found, result.duration));
+    if s.dirs > 0 {
+        out += &format!("  Throughput: {:.0} dirs/s, {}\n", s.dirs_per_sec(), files);
+        out += &format!(
+            "  Walked: {} dirs, {} entries, {} pruned by excludes\n",
+            s.dirs, s.entries, s.pruned
+        );
+    } else {
+        out += &format!("  Throughput: {}\n", files);
+    }
+    let found: Vec<String> = s.by_type.iter().map(|(t, n)| format!("{} {}", n, t.name())).collect();
+    if !found.is_empty() {
+        out += &format!("  Found: {}\n", found.join(", "));
+    }
+    out += &format!("  Reclaimed: {}\n", human_bytes(s.bytes));
+    out += &format!(
+        "  Skipped: {} git-tracked, {} protected, {} by age/size\n",
+        result.skipped.len(),
+        result.protected.len(),
+        s.filtered
+    );
+    out += &format!("  Errors: {}\n", result.failures.len());
+    if let Some(hit) = s.cache_hit {
+        out += &format!("  Cache: {}\n", if hit { "hit" } else { "miss" });
+    }
+    out
+}
+
+/// Format a byte count with a 1024-based unit
+fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Send macOS native notification
+pub fn notify(message: &str) {
+    let script = format!(
+        r#"display notification "{}" with title "🗑️ dsk""#,
+        message.replace('"', r#"\""#)
+    );
+    let _ = Command::new("osascript")
+        .args(["-e", &script])
+        .output();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(6148), "6.0 KiB");
+        assert_eq!(human_bytes(3 << 30), "3.0 GiB");
+    }
+}
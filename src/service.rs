@@ -1,7 +1,14 @@
 //! launchd service management
 
-use crate::{consts::*, log};
-use std::{env, fs, io::Write, path::PathBuf, process::Command};
+use crate::{config, consts::*, log, pause, watcher, xml};
+use std::{
+    env,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
 
 fn home_dir() -> PathBuf {
     shellexpand::tilde("~").to_string().into()
@@ -15,14 +22,95 @@ fn expand(path: &str) -> String {
     shellexpand::tilde(path).to_string()
 }
 
-fn generate_plist(exe: &str, paths: &[String], args: &crate::cli::WatchSharedArgs) -> String {
+/// launchd arguments for `dsk watch`
+///
+/// Only flags given to `install` are embedded; everything else is read from
+/// `config` each time the service starts. `notify` is the effective setting,
+/// which decides whether the job needs a GUI session.
+fn generate_plist(
+    exe: &str,
+    paths: &[String],
+    args: &crate::cli::WatchSharedArgs,
+    config: Option<&Path>,
+    notify: bool,
+) -> String {
     let mut flags = Vec::new();
+    if let Some(file) = config {
+        flags.push("--config".to_string());
+        flags.push(file.to_string_lossy().into_owned());
+    }
     if args.notify { flags.push("--notify".to_string()); }
+    if args.no_notify { flags.push("--no-notify".to_string()); }
     if args.force { flags.push("--force".to_string()); }
+    if args.no_force { flags.push("--no-force".to_string()); }
+    if let Some(ms) = args.debounce {
+        flags.push("--debounce".to_string());
+        flags.push(ms.to_string());
+    }
+    if let Some(secs) = args.sweep {
+        flags.push("--sweep".to_string());
+        flags.push(secs.to_string());
+    }
+    if let Some(secs) = args.poll {
+        flags.push("--poll".to_string());
+        flags.push(secs.to_string());
+    }
+    for p in &args.poll_path {
+        flags.push("--poll-path".to_string());
+        flags.push(expand(&p.to_string_lossy()));
+    }
+    if args.no_auto_poll {
+        flags.push("--no-auto-poll".to_string());
+    }
+    if args.full_scan {
+        flags.push("--full-scan".to_string());
+    }
+    if args.traversal.one_file_system {
+        flags.push("--one-file-system".to_string());
+    }
+    if args.traversal.no_one_file_system {
+        flags.push("--no-one-file-system".to_string());
+    }
+    if args.traversal.follow_symlinks {
+        flags.push("--follow-symlinks".to_string());
+    }
+    if args.traversal.no_follow_symlinks {
+        flags.push("--no-follow-symlinks".to_string());
+    }
+    if let Some(n) = args.traversal.max_depth {
+        flags.push("--max-depth".to_string());
+        flags.push(n.to_string());
+    }
+    if let Some(n) = args.traversal.min_depth {
+        flags.push("--min-depth".to_string());
+        flags.push(n.to_string());
+    }
+    if let Some(sock) = &args.socket {
+        flags.push("--socket".to_string());
+        flags.push(expand(&sock.to_string_lossy()));
+    }
     for ex in &args.exclude {
         flags.push("-e".to_string());
         flags.push(ex.clone());
     }
+    let f = &args.filter;
+    let durations = [("--older-than", f.older_than), ("--newer-than", f.newer_than)];
+    for (flag, d) in durations {
+        if let Some(d) = d {
+            flags.push(flag.to_string());
+            flags.push(d.as_secs().to_string());
+        }
+    }
+    for (flag, size) in [("--min-size", f.min_size), ("--max-size", f.max_size)] {
+        if let Some(n) = size {
+            flags.push(flag.to_string());
+            flags.push(n.to_string());
+        }
+    }
+    for k in &args.keep {
+        flags.push("--keep".to_string());
+        flags.push(expand(k));
+    }
 
     // Note: Use 'watch' subcommand instead of deprecated --serve
     let mut cmd_args = vec![
@@ -30,17 +118,20 @@ fn generate_plist(exe: &str, paths: &[String], args: &crate::cli::WatchSharedArg
     ];
 
     for flag in flags {
-        cmd_args.push(format!("<string>{}</string>", flag));
+        cmd_args.push(format!("<string>{}</string>", xml::escape(&flag)));
     }
 
+    // Paths last, after `--`, so none can be mistaken for a flag
+    cmd_args.push("<string>--</string>".to_string());
+
     for p in paths {
-        cmd_args.push(format!("<string>{}</string>", expand(p)));
+        cmd_args.push(format!("<string>{}</string>", xml::escape(&expand(p))));
     }
 
     let args_xml = cmd_args.join("\n        ");
 
     // Only require Aqua session if notifications are enabled
-    let session_xml = if args.notify {
+    let session_xml = if notify {
         r#"    <key>LimitLoadToSessionType</key>
     <string>Aqua</string>
 "#
@@ -48,6 +139,7 @@ fn generate_plist(exe: &str, paths: &[String], args: &crate::cli::WatchSharedArg
         ""
     };
 
+    let exe = xml::escape(exe);
     format!(
 r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
@@ -73,7 +165,11 @@ r#"<?xml version="1.0" encoding="UTF-8"?>
 "#)
 }
 
-pub fn install(paths: &[String], args: &crate::cli::WatchSharedArgs) -> Result<(), String> {
+pub fn install(
+    paths: &[String],
+    args: &crate::cli::WatchSharedArgs,
+    config: Option<&Path>,
+) -> Result<(), String> {
     let exe = env::current_exe().map_err(|e| e.to_string())?;
     let plist = plist_path();
 
@@ -81,20 +177,63 @@ pub fn install(paths: &[String], args: &crate::cli::WatchSharedArgs) -> Result<(
         fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
 
-    let watch: Vec<String> = if paths.is_empty() {
+    let requested: Vec<String> = if paths.is_empty() {
         vec!["~".into()]
     } else {
         paths.to_vec()
     };
 
-    let content = generate_plist(&exe.to_string_lossy(), &watch, args);
+    // launchd runs from `/`, so store absolute paths that exist now
+    let mut resolved = Vec::new();
+    for p in &requested {
+        let path = PathBuf::from(expand(p));
+        match path.canonicalize() {
+            Ok(abs) if abs.is_dir() => resolved.push(abs),
+            _ => return Err(format!("Not a directory: {}", path.display())),
+        }
+    }
+    for p in &args.poll_path {
+        let path = PathBuf::from(expand(&p.to_string_lossy()));
+        if !path.is_dir() {
+            return Err(format!("Not a directory: {}", path.display()));
+        }
+    }
+    let watch: Vec<String> = watcher::distinct_roots(&resolved)
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+
+    for file in config::ignored_local_files(&resolved) {
+        log::warn(&format!(
+            "Ignoring {}: watch options come from the first root's config",
+            log::shorten_path(&file)
+        ));
+    }
+
+    // launchd doesn't see the shell's DSK_CONFIG/XDG_CONFIG_HOME, so name
+    // the config file whenever there is one to name
+    let (file, explicit) = config::user_file(config);
+    let file = (explicit || file.is_file()).then(|| file.canonicalize().unwrap_or(file));
+    let effective = config::resolve(file.as_deref(), &resolved[0], args.flags())
+        .map_err(|e| format!("Invalid configuration: {}", e))?;
+
+    let content = generate_plist(
+        &exe.to_string_lossy(),
+        &watch,
+        args,
+        file.as_deref(),
+        effective.notify.unwrap_or(false),
+    );
     fs::File::create(&plist)
         .and_then(|mut f| f.write_all(content.as_bytes()))
         .map_err(|e| e.to_string())?;
 
     log::ok("Service installed:");
     println!("  Plist: {}", log::shorten_path(&plist));
-    println!("  Watch: {}", watch.iter().map(|p| expand(p)).collect::<Vec<_>>().join(", "));
+    println!("  Watch: {}", watch.join(", "));
+    if let Some(file) = &file {
+        println!("  Config: {}", log::shorten_path(file));
+    }
 
     let mut opts = Vec::new();
     if args.notify { opts.push("notify".to_string()); }
@@ -186,6 +325,21 @@ pub fn status() -> Result<(), String> {
         .map_err(|e| e.to_string())?;
 
     println!("Running:   {}", if out.status.success() { "Yes" } else { "No" });
+
+    let now = SystemTime::now();
+    let pauses = pause::load(&pause::file(), now);
+    if pauses.is_empty() {
+        println!("Paused:    No");
+    } else {
+        println!("Paused:");
+        for p in &pauses {
+            println!(
+                "  {} ({} left)",
+                log::shorten_path(&p.path),
+                pause::format_duration(p.remaining(now))
+            );
+        }
+    }
     println!("\nLogs:");
     println!("  stdout: {}", LOG_STDOUT);
     println!("  stderr: {}", LOG_STDERR);
@@ -207,11 +361,89 @@ mod tests {
         let args = crate::cli::WatchSharedArgs {
             exclude: vec![],
             notify: false,
+            no_notify: false,
             force: false,
+            no_force: false,
+            keep: vec![],
+            filter: Default::default(),
+            traversal: Default::default(),
+            debounce: None,
+            sweep: None,
+            poll: None,
+            poll_path: vec![],
+            no_auto_poll: false,
+            full_scan: false,
+            socket: None,
         };
-        let plist = generate_plist("/bin/dsk", &["~".into()], &args);
+        let plist = generate_plist("/bin/dsk", &["~".into()], &args, None, false);
         assert!(plist.contains(SERVICE_ID));
         assert!(plist.contains("watch"));
         assert!(!plist.contains("--serve"));
+        assert!(!plist.contains("--config"));
+        assert!(!plist.contains("Aqua"));
+
+        // Config options are referenced, not embedded; notify from the
+        // config still needs a GUI session
+        let config = Path::new("/u/.config/dsk/config.toml");
+        let plist = generate_plist("/bin/dsk", &["~".into()], &args, Some(config), true);
+        assert!(plist.contains("<string>--config</string>\n        <string>/u/.config/dsk/config.toml</string>"));
+        assert!(!plist.contains("--notify"));
+        assert!(plist.contains("Aqua"));
+    }
+
+    #[test]
+    fn test_plist_multiple_paths() {
+        let args = crate::cli::WatchSharedArgs {
+            exclude: vec!["build".into()],
+            notify: true,
+            no_notify: false,
+            force: false,
+            no_force: false,
+            keep: vec![],
+            filter: Default::default(),
+            traversal: Default::default(),
+            debounce: None,
+            sweep: None,
+            poll: None,
+            poll_path: vec![],
+            no_auto_poll: false,
+            full_scan: false,
+            socket: None,
+        };
+        let plist = generate_plist("/bin/dsk", &["/a".into(), "/b".into()], &args, None, true);
+        let strings: Vec<&str> = plist
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("<string>")?.strip_suffix("</string>"))
+            .collect();
+        let start = strings.iter().position(|s| *s == "watch").unwrap();
+        assert_eq!(
+            &strings[start..start + 7],
+            &["watch", "--notify", "-e", "build", "--", "/a", "/b"]
+        );
+    }
+
+    #[test]
+    fn test_plist_escapes_values() {
+        let args = crate::cli::WatchSharedArgs {
+            exclude: vec![],
+            notify: false,
+            no_notify: false,
+            force: false,
+            no_force: false,
+            keep: vec!["/Design/R&D <old>".into()],
+            filter: Default::default(),
+            traversal: Default::default(),
+            debounce: None,
+            sweep: None,
+            poll: None,
+            poll_path: vec![],
+            no_auto_poll: false,
+            full_scan: false,
+            socket: None,
+        };
+        let plist = generate_plist("/bin/dsk", &["/Tom & Jerry".into()], &args, None, false);
+        assert!(plist.contains("<string>/Design/R&amp;D &lt;old&gt;</string>"));
+        assert!(plist.contains("<string>/Tom &amp; Jerry</string>"));
+        assert!(!plist.contains("R&D"));
     }
 }
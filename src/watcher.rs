@@ -1,58 +1,556 @@
 //! File system watcher for daemon mode
 
-use crate::{consts::TARGET_FILE, git, log};
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::{fs, path::Path, process::Command, sync::mpsc::channel};
+use crate::{
+    consts::{POLL_SECS, ROOT_CHECK_SECS},
+    engine::{Action, Engine, FsEvent, Outcome, WatchStats},
+    ctl::{self, Reply, Request},
+    git, json,
+    killer::{Filter, Scanner, Skip, Traversal, is_excluded},
+    log, pause, poll,
+    protect::Protector,
+    report,
+};
+use notify::{
+    Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{CreateKind, ModifyKind, RenameMode},
+};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, RecvTimeoutError, Sender, channel},
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-/// Check if path matches any exclude pattern
-#[inline]
-fn is_excluded(path: &Path, excludes: &[String]) -> bool {
-    let s = path.to_string_lossy();
-    excludes.iter().any(|p| s.contains(p))
+/// Watch events reported in `--format ndjson` mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A target file was seen
+    Detected,
+    /// The target file was removed
+    Deleted,
+    /// Left in place because it is tracked by git
+    SkippedGit,
+    /// Ignored because it matches an exclude pattern
+    Excluded,
+    /// Left in place by a protection rule
+    Protected,
+    /// Left in place by an age or size filter
+    Filtered,
+    /// Deletion or the watcher backend failed
+    Error,
+}
+
+impl WatchEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Detected => "detected",
+            Self::Deleted => "deleted",
+            Self::SkippedGit => "skipped-git",
+            Self::Excluded => "excluded",
+            Self::Protected => "protected",
+            Self::Filtered => "filtered",
+            Self::Error => "error",
+        }
+    }
 }
 
-/// Send macOS native notification
-fn send_notification(message: &str) {
-    let script = format!(
-        r#"display notification "{}" with title "🗑️ dsk""#,
-        message.replace('"', r#"\""#)
-    );
-    let _ = Command::new("osascript")
-        .args(["-e", &script])
-        .output();
+/// Encode a watch event as a single NDJSON line
+pub fn event_json(event: WatchEvent, path: Option<&Path>, message: Option<&str>) -> String {
+    let mut obj = json::Object::new()
+        .str("event", event.as_str())
+        .num("ts", json::now_millis());
+    if let Some(p) = path {
+        obj = obj.path("path", p);
+    }
+    if let Some(m) = message {
+        obj = obj.str("message", m);
+    }
+    obj.finish()
 }
 
-/// Attempt to delete a .DS_Store file with git safety check
-/// Returns true if file was deleted, false if skipped
-fn try_delete(path: &Path, force: bool, notify: bool) -> bool {
+/// Print an event line when NDJSON output is enabled
+#[inline]
+fn emit(ndjson: bool, event: WatchEvent, path: Option<&Path>, message: Option<&str>) {
+    if ndjson {
+        println!("{}", event_json(event, path, message));
+    }
+}
+
+/// Attempt to delete a .DS_Store file with protection and git safety checks
+fn try_delete(path: &Path, opts: &WatchOptions) -> Outcome {
+    let WatchOptions { force, notify, ndjson, .. } = *opts;
+
+    if let Some(reason) = opts.protect.check(path) {
+        log::keep(path, &reason);
+        emit(ndjson, WatchEvent::Protected, Some(path), Some(&reason.to_string()));
+        return Outcome::Protected;
+    }
+
+    if let Err(skip) = opts.filter.check(path) {
+        if let Skip::Large(_) = skip {
+            log::warn(&format!("Left in place, {}: {}", skip, path.display()));
+        }
+        emit(ndjson, WatchEvent::Filtered, Some(path), Some(&skip.to_string()));
+        // Deleted once it has aged enough, unless it changes again first
+        if skip == Skip::New
+            && let Some(wait) = opts.filter.wait(path)
+        {
+            return Outcome::TooNew(wait);
+        }
+        return Outcome::Filtered;
+    }
+
     // Git safety check
     if !force && git::is_available() && git::is_git_tracked(path) {
         log::warn(&format!("Skipping git-tracked: {}", path.display()));
-        return false;
+        emit(ndjson, WatchEvent::SkippedGit, Some(path), None);
+        return Outcome::SkippedGit;
     }
 
     log::kill(path);
     match fs::remove_file(path) {
         Ok(()) => {
+            emit(ndjson, WatchEvent::Deleted, Some(path), None);
             if notify {
-                send_notification(&format!("Killed {}", log::shorten_path(path)));
+                report::notify(&format!("Killed {}", log::shorten_path(path)));
             }
-            true
+            Outcome::Deleted
         }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => true, // Already gone
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Outcome::Gone,
         Err(e) => {
             log::warn(&format!("Failed to remove: {}", e));
-            false
+            emit(ndjson, WatchEvent::Error, Some(path), Some(&e.to_string()));
+            Outcome::Failed
+        }
+    }
+}
+
+/// Watch mode settings
+#[derive(Clone)]
+pub struct WatchOptions {
+    pub excludes: Vec<String>,
+    pub notify: bool,
+    pub force: bool,
+    pub ndjson: bool,
+    /// Quiet period a path must reach before it is acted on
+    pub debounce: Duration,
+    /// Periodic full rescan of every root, if enabled
+    pub sweep: Option<Duration>,
+    /// Poll interval; alone it makes every root polled
+    pub poll: Option<Duration>,
+    /// Roots to poll instead of using kernel events
+    pub poll_paths: Vec<PathBuf>,
+    /// Poll roots on network/FUSE file systems automatically
+    pub auto_poll: bool,
+    /// Ignore saved checkpoints and scan every root on start
+    pub full_scan: bool,
+    /// Control socket to serve, if any
+    pub socket: Option<PathBuf>,
+    /// Subtree pauses still active from earlier `dsk pause` calls
+    pub pauses: Vec<pause::Pause>,
+    /// Rules for targets that must be left alone
+    pub protect: Protector,
+    /// Age and size conditions for deletion
+    pub filter: Filter,
+    /// File system boundaries for scans
+    pub traversal: Traversal,
+}
+
+/// How a root is watched
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Events,
+    Poll { every: Duration, reason: String },
+}
+
+/// Decide between kernel events and polling for `root`
+///
+/// `fs_type` is the root's file system type, if known.
+fn watch_mode(root: &Path, fs_type: Option<&str>, opts: &WatchOptions) -> Mode {
+    let every = opts.poll.unwrap_or(Duration::from_secs(POLL_SECS));
+
+    if opts.poll_paths.iter().any(|p| p == root) {
+        return Mode::Poll { every, reason: "--poll-path".into() };
+    }
+    if opts.poll.is_some() && opts.poll_paths.is_empty() {
+        return Mode::Poll { every, reason: "--poll".into() };
+    }
+    match fs_type {
+        Some(t) if opts.auto_poll && poll::needs_polling(t) => {
+            Mode::Poll { every, reason: t.to_string() }
+        }
+        _ => Mode::Events,
+    }
+}
+
+/// Act on a debounced target path: exclude check, git check, delete
+fn handle_path(path: &Path, opts: &WatchOptions) -> Outcome {
+    if !path.exists() {
+        return Outcome::Gone;
+    }
+
+    emit(opts.ndjson, WatchEvent::Detected, Some(path), None);
+    if is_excluded(path, &opts.excludes) {
+        emit(opts.ndjson, WatchEvent::Excluded, Some(path), None);
+        return Outcome::Excluded;
+    }
+
+    try_delete(path, opts)
+}
+
+/// Message delivered to the driver loop
+pub enum Msg {
+    Fs(FsEvent),
+    /// Control socket request, answered through the sender
+    Ctl(Request, Sender<Reply>),
+    /// SIGINT/SIGTERM received
+    Shutdown,
+}
+
+/// Classify a path that appeared in the tree: directory or plain entry
+fn appeared(path: PathBuf) -> FsEvent {
+    if path.is_dir() {
+        FsEvent::DirCreated(path)
+    } else {
+        FsEvent::Created(path)
+    }
+}
+
+/// Convert a notify event into backend-independent events
+///
+/// Paths that appear via create or rename-into are checked on disk so that
+/// directories moved or extracted into the tree get a sub-scan.
+pub fn translate(event: Event) -> Vec<FsEvent> {
+    if event.need_rescan() {
+        return vec![FsEvent::Rescan];
+    }
+
+    let mut paths = event.paths.into_iter();
+
+    match event.kind {
+        EventKind::Create(CreateKind::Folder) => paths.map(FsEvent::DirCreated).collect(),
+        EventKind::Create(_) => paths.map(appeared).collect(),
+        EventKind::Remove(_) => paths.map(FsEvent::Removed).collect(),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            match (paths.next(), paths.next()) {
+                (Some(from), Some(to)) if to.is_dir() => {
+                    vec![FsEvent::Removed(from), FsEvent::DirCreated(to)]
+                }
+                (Some(from), Some(to)) => vec![FsEvent::Renamed { from, to }],
+                (Some(p), None) => vec![appeared(p)],
+                _ => Vec::new(),
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.map(FsEvent::Removed).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => paths.map(appeared).collect(),
+        // FSEvents reports renames without a direction: a directory that
+        // exists now was moved in, anything else is checked by the handler.
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .map(|p| if p.is_dir() { FsEvent::DirCreated(p) } else { FsEvent::Modified(p) })
+            .collect(),
+        EventKind::Modify(_) | EventKind::Any => paths.map(FsEvent::Modified).collect(),
+        EventKind::Access(_) | EventKind::Other => Vec::new(),
+    }
+}
+
+/// Side effects the driver delegates to, so tests can substitute fakes
+trait Executor {
+    /// Check and delete a single target
+    fn handle(&mut self, path: &Path) -> Outcome;
+    /// Find targets in a subtree, within `traversal` relative to `dir`
+    fn scan(&mut self, dir: &Path, traversal: Traversal) -> Vec<PathBuf>;
+    /// Whether a watched root is currently present
+    fn root_exists(&mut self, root: &Path) -> bool;
+    /// Register a root with the backend
+    fn watch(&mut self, root: &Path) -> Result<(), String>;
+    /// Deregister a root from the backend
+    fn unwatch(&mut self, root: &Path);
+}
+
+/// Executor that touches the real file system
+struct Live<'a, W: Watcher> {
+    opts: &'a WatchOptions,
+    watcher: &'a mut W,
+    /// Roots handled by a poller rather than the backend
+    polled: Vec<PathBuf>,
+}
+
+impl<W: Watcher> Executor for Live<'_, W> {
+    fn handle(&mut self, path: &Path) -> Outcome {
+        handle_path(path, self.opts)
+    }
+
+    fn scan(&mut self, dir: &Path, traversal: Traversal) -> Vec<PathBuf> {
+        if !dir.is_dir() || is_excluded(dir, &self.opts.excludes) {
+            return Vec::new();
+        }
+        // Filters are applied per file by `try_delete`, so skips are counted
+        Scanner::new(dir).recursive(true).excludes(&self.opts.excludes).traversal(traversal).scan()
+    }
+
+    fn root_exists(&mut self, root: &Path) -> bool {
+        root.is_dir()
+    }
+
+    fn watch(&mut self, root: &Path) -> Result<(), String> {
+        if self.polled.iter().any(|p| p == root) {
+            return Ok(());
+        }
+        self.watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|e| e.to_string())
+    }
+
+    fn unwatch(&mut self, root: &Path) {
+        if self.polled.iter().any(|p| p == root) {
+            return;
+        }
+        // The backend may already have dropped it along with the directory
+        let _ = self.watcher.unwatch(root);
+    }
+}
+
+/// Carry out engine actions, feeding follow-up work back into the engine
+fn perform<E: Executor>(engine: &mut Engine, exec: &mut E, actions: Vec<Action>, ndjson: bool) {
+    let mut work: VecDeque<Action> = actions.into();
+
+    while let Some(action) = work.pop_front() {
+        match action {
+            Action::Handle(path) => {
+                if !engine.is_paused_at(&path) {
+                    let outcome = exec.handle(&path);
+                    engine.record(&path, outcome, Instant::now());
+                }
+            }
+            Action::Scan(dir) => {
+                for path in exec.scan(&dir, engine.traversal_at(&dir)) {
+                    if !engine.is_paused_at(&path) {
+                        let outcome = exec.handle(&path);
+                        engine.record(&path, outcome, Instant::now());
+                    }
+                }
+            }
+            Action::CheckRoots => {
+                for root in engine.roots() {
+                    let up = exec.root_exists(&root);
+                    work.extend(engine.root_status(&root, up));
+                }
+            }
+            Action::Watch(root) => match exec.watch(&root) {
+                Ok(()) => log::watch(&format!("Root is back, watching again: {}", root.display())),
+                Err(e) => {
+                    log::error(&format!("Cannot watch {}: {}", root.display(), e));
+                    // Retried on the next health check
+                    engine.root_status(&root, false);
+                }
+            },
+            Action::Unwatch(root) => {
+                log::warn(&format!("Root disappeared: {}", root.display()));
+                exec.unwatch(&root);
+            }
+            Action::Error(msg) => {
+                log::error(&msg);
+                emit(ndjson, WatchEvent::Error, None, Some(&msg));
+            }
+        }
+    }
+}
+
+/// Run the engine over a message stream until shutdown or disconnect
+///
+/// Released targets and sub-scan results go through `exec`, which applies
+/// the exclude and git rules. Pending work is flushed before returning.
+fn drive<E: Executor>(rx: &Receiver<Msg>, engine: &mut Engine, ndjson: bool, exec: &mut E) {
+    let started = Instant::now();
+    loop {
+        let received = match engine.next_deadline() {
+            Some(deadline) => {
+                rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        let actions = match received {
+            Ok(Msg::Fs(event)) => engine.on_event(event, Instant::now()),
+            Ok(Msg::Ctl(request, reply)) => {
+                let _ = reply.send(control(engine, exec, request, started.elapsed(), ndjson));
+                Vec::new()
+            }
+            Ok(Msg::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => Vec::new(),
+        };
+        perform(engine, exec, actions, ndjson);
+
+        let ready = engine.on_tick(Instant::now());
+        perform(engine, exec, ready, ndjson);
+    }
+
+    let rest = engine.shutdown();
+    perform(engine, exec, rest, ndjson);
+}
+
+/// Answer a control request, carrying out any work it triggers
+fn control<E: Executor>(
+    engine: &mut Engine,
+    exec: &mut E,
+    request: Request,
+    uptime: Duration,
+    ndjson: bool,
+) -> Reply {
+    match request {
+        Request::Stats => Ok(status_json(engine, uptime)),
+        Request::Pause => {
+            engine.pause();
+            log::watch("Paused");
+            Ok("paused".into())
+        }
+        Request::Resume => {
+            engine.resume(Instant::now());
+            log::watch("Resumed, rescanning roots");
+            Ok("resumed".into())
+        }
+        Request::Rescan(dir) => {
+            let actions = engine.rescan(&dir)?;
+            perform(engine, exec, actions, ndjson);
+            Ok(format!("rescanned {}", dir.display()))
+        }
+        Request::AddRoot(root) => {
+            if !exec.root_exists(&root) {
+                return Err(format!("Not a directory: {}", root.display()));
+            }
+            engine.add_root(&root)?;
+            if let Err(e) = exec.watch(&root) {
+                engine.remove_root(&root);
+                return Err(e);
+            }
+            log::watch(&format!("Added root: {}", root.display()));
+            if !engine.is_paused() {
+                perform(engine, exec, vec![Action::Scan(root.clone())], ndjson);
+            }
+            Ok(format!("watching {}", root.display()))
+        }
+        Request::PausePath(path, duration) => {
+            let until = Instant::now()
+                .checked_add(duration)
+                .filter(|_| duration <= pause::MAX_DURATION)
+                .ok_or_else(|| format!("pause too long: {}s", duration.as_secs()))?;
+            engine.pause_path(&path, until);
+            log::watch(&format!(
+                "Paused for {}: {}",
+                pause::format_duration(duration),
+                path.display()
+            ));
+            Ok(format!("paused {}", path.display()))
+        }
+        Request::ResumePath(path) => {
+            if !engine.resume_path(&path, Instant::now()) {
+                return Err(format!("not paused: {}", path.display()));
+            }
+            log::watch(&format!("Resumed: {}", path.display()));
+            Ok(format!("resumed {}", path.display()))
+        }
+        Request::RemoveRoot(root) => {
+            if !engine.remove_root(&root) {
+                return Err(format!("not a watched root: {}", root.display()));
+            }
+            exec.unwatch(&root);
+            log::watch(&format!("Removed root: {}", root.display()));
+            Ok(format!("stopped watching {}", root.display()))
+        }
+    }
+}
+
+/// Append lifetime counters to a JSON object
+fn with_counters(obj: json::Object, stats: &WatchStats) -> json::Object {
+    obj.num("events", stats.events)
+        .num("deleted", stats.deleted)
+        .num("skipped_git", stats.skipped_git)
+        .num("excluded", stats.excluded)
+        .num("protected", stats.protected)
+        .num("filtered", stats.filtered)
+        .num("errors", stats.errors)
+        .num("rescans", stats.rescans)
+}
+
+/// Encode final watcher statistics as an NDJSON `stopped` line
+fn stats_json(stats: &WatchStats) -> String {
+    let obj = json::Object::new()
+        .str("event", "stopped")
+        .num("ts", json::now_millis());
+    with_counters(obj, stats).finish()
+}
+
+/// Encode the live state of the watcher for `dsk ctl stats`
+fn status_json(engine: &Engine, uptime: Duration) -> String {
+    let roots = engine.root_states().into_iter().map(|(path, up)| {
+        json::Object::new().path("path", &path).bool("up", up).finish()
+    });
+    let paused_paths = engine.paused_paths(Instant::now()).into_iter().map(|(path, left)| {
+        json::Object::new().path("path", &path).num("remaining_secs", left.as_secs()).finish()
+    });
+    let obj = json::Object::new()
+        .bool("paused", engine.is_paused())
+        .raw("paused_paths", &json::array(paused_paths))
+        .num("uptime_secs", uptime.as_secs())
+        .num("queue", engine.queued() as u64)
+        .raw("roots", &json::array(roots));
+    with_counters(obj, engine.stats()).finish()
+}
+
+/// Delete a target found before the event loop starts
+fn initial_delete(path: &Path, opts: &WatchOptions, engine: &mut Engine) {
+    if is_excluded(path, &opts.excludes) || engine.is_paused_at(path) || !engine.in_depth(path) {
+        return;
+    }
+    emit(opts.ndjson, WatchEvent::Detected, Some(path), None);
+    engine.record(path, try_delete(path, opts), Instant::now());
+}
+
+/// Delete every target under `dir` before the event loop starts
+fn initial_scan(dir: &Path, opts: &WatchOptions, engine: &mut Engine) {
+    let scanner = Scanner::new(dir)
+        .recursive(true)
+        .excludes(&opts.excludes)
+        .traversal(engine.traversal_at(dir));
+    scanner.for_each(|path| {
+        if engine.is_paused_at(path) {
+            return;
         }
+        emit(opts.ndjson, WatchEvent::Detected, Some(path), None);
+        engine.record(path, try_delete(path, opts), Instant::now());
+    });
+}
+
+/// Drop duplicate roots and roots nested inside another root
+///
+/// A recursive watch on the outer root already covers the inner one; keeping
+/// both would report every event twice. Order of first appearance is kept.
+pub fn distinct_roots(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for p in paths {
+        if paths.iter().any(|q| q != p && p.starts_with(q)) || roots.contains(p) {
+            continue;
+        }
+        roots.push(p.clone());
     }
+    roots
 }
 
 /// Watch directories and auto-delete .DS_Store files
-pub fn run(paths: &[&Path], mut excludes: Vec<String>, notify: bool, force: bool) -> Result<(), String> {
+///
+/// Runs until SIGINT/SIGTERM, then flushes pending targets and prints
+/// final statistics.
+pub fn run(paths: &[&Path], mut opts: WatchOptions) -> Result<(), String> {
     // Add default excludes
     for d in ["node_modules", ".git", "target"] {
-        if !excludes.iter().any(|e| e == d) {
-            excludes.push(d.to_string());
+        if !opts.excludes.iter().any(|e| e == d) {
+            opts.excludes.push(d.to_string());
         }
     }
 
@@ -64,55 +562,495 @@ pub fn run(paths: &[&Path], mut excludes: Vec<String>, notify: bool, force: bool
 
     // Initialize watcher first to capture all events (eliminates vacuum period)
     let (tx, rx) = channel();
-    let mut watcher =
-        RecommendedWatcher::new(tx, Config::default()).map_err(|e| e.to_string())?;
+    let fs_tx = tx.clone();
+    let mut watcher = RecommendedWatcher::new(
+        move |res: notify::Result<Event>| {
+            let events = match res {
+                Ok(event) => translate(event),
+                Err(e) => vec![FsEvent::Error(e.to_string())],
+            };
+            for event in events {
+                let _ = fs_tx.send(Msg::Fs(event));
+            }
+        },
+        Config::default(),
+    )
+    .map_err(|e| e.to_string())?;
 
-    for p in paths {
-        watcher
-            .watch(p, RecursiveMode::Recursive)
-            .map_err(|e| e.to_string())?;
-    }
+    let signal_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = signal_tx.send(Msg::Shutdown);
+    })
+    .map_err(|e| e.to_string())?;
 
     log::watch("Watching for .DS_Store files...");
+    let mut polled = Vec::new();
     for p in paths {
-        println!("  {}", p.display());
+        match watch_mode(p, poll::fs_type(p).as_deref(), &opts) {
+            Mode::Events => {
+                watcher
+                    .watch(p, RecursiveMode::Recursive)
+                    .map_err(|e| e.to_string())?;
+                log::detail(&p.display().to_string());
+            }
+            Mode::Poll { every, reason } => {
+                polled.push((p.to_path_buf(), every));
+                log::detail(&format!("{} (polling every {:?}, {})", p.display(), every, reason));
+            }
+        }
     }
 
-    // Initial cleanup (events buffered in channel during scan)
-    log::watch("Performing initial cleanup...");
-    for p in paths {
-        crate::killer::scan_streaming(p, true, &excludes, |path| {
-            try_delete(path, force, notify);
-        });
+    let now = Instant::now();
+    let roots: Vec<PathBuf> = paths.iter().map(|p| p.to_path_buf()).collect();
+    let mut engine = Engine::new(opts.debounce)
+        .with_roots(&roots, Duration::from_secs(ROOT_CHECK_SECS), now)
+        .with_traversal(opts.traversal.clone());
+    if let Some(every) = opts.sweep {
+        engine = engine.with_sweep(every, now);
+    }
+    let wall = SystemTime::now();
+    for p in &opts.pauses {
+        let remaining = p.remaining(wall).min(pause::MAX_DURATION);
+        let Some(until) = now.checked_add(remaining) else { continue };
+        engine.pause_path(&p.path, until);
+        log::watch(&format!(
+            "Paused for {}: {}",
+            pause::format_duration(remaining),
+            p.path.display()
+        ));
     }
 
-    println!("Press Ctrl+C to stop.");
-
-    // Event loop
-    loop {
-        match rx.recv() {
-            Ok(Ok(event)) => {
-                if !matches!(
-                    event.kind,
-                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
-                ) {
-                    continue;
-                }
+    // Initial cleanup (events buffered in channel during scan). Directory
+    // state is recorded before scanning so nothing created meanwhile is missed.
+    log::watch("Performing initial cleanup...");
+    let mut pollers = Vec::new();
+    for p in paths {
+        let checkpoint = poll::checkpoint_path(p);
+        let resumed = (!opts.full_scan)
+            .then(|| poll::Poller::load(&checkpoint, p, &opts.excludes, &opts.traversal))
+            .flatten();
 
-                for path in event.paths {
-                    if path.file_name().map_or(false, |n| n == TARGET_FILE)
-                        && !is_excluded(&path, &excludes)
-                        && path.exists()
-                    {
-                        try_delete(&path, force, notify);
+        let poller = match resumed {
+            Some(mut poller) => {
+                log::watch(&format!("Resuming from checkpoint: {}", p.display()));
+                for event in poller.poll() {
+                    match event {
+                        FsEvent::Created(path) => initial_delete(&path, &opts, &mut engine),
+                        FsEvent::DirCreated(dir) => initial_scan(&dir, &opts, &mut engine),
+                        _ => {}
                     }
                 }
+                poller
             }
-            Ok(Err(e)) => log::error(&e.to_string()),
-            Err(_) => break,
+            None => {
+                // One walk both records the tree and finds what to delete
+                let (poller, found) = poll::Poller::new(p, &opts.excludes, &opts.traversal);
+                for path in found {
+                    initial_delete(&path, &opts, &mut engine);
+                }
+                poller
+            }
+        };
+        pollers.push((p.to_path_buf(), checkpoint, Arc::new(Mutex::new(poller))));
+    }
+
+    for (root, every) in &polled {
+        let Some((_, _, poller)) = pollers.iter().find(|(p, _, _)| p == root) else { continue };
+        let tx = tx.clone();
+        poll::spawn(poller.clone(), *every, move |event| tx.send(Msg::Fs(event)).is_ok());
+    }
+    let polled: Vec<PathBuf> = polled.into_iter().map(|(p, _)| p).collect();
+
+    // Requests are relayed to the driver loop, which owns all state
+    let ctl_tx = tx.clone();
+    let _server = opts.socket.as_deref().and_then(|sock| {
+        let server = ctl::serve(sock, move |request| {
+            let (reply_tx, reply_rx) = channel();
+            ctl_tx
+                .send(Msg::Ctl(request, reply_tx))
+                .map_err(|_| "watcher is shutting down".to_string())?;
+            reply_rx.recv().map_err(|_| "watcher is shutting down".to_string())?
+        });
+        match server {
+            Ok(server) => Some(server),
+            Err(e) => {
+                log::warn(&format!("Control socket disabled: {}", e));
+                None
+            }
+        }
+    });
+
+    log::watch("Press Ctrl+C to stop.");
+
+    let mut live = Live { opts: &opts, watcher: &mut watcher, polled };
+    drive(&rx, &mut engine, opts.ndjson, &mut live);
+
+    drop(watcher);
+    for (root, checkpoint, poller) in &pollers {
+        let saved = poller
+            .lock()
+            .map_err(|e| e.to_string())
+            .and_then(|mut p| p.save(checkpoint));
+        if let Err(e) = saved {
+            log::warn(&format!("Cannot save checkpoint for {}: {}", root.display(), e));
         }
     }
+    log::watch(&format!("Stopped: {}", engine.stats()));
+    if opts.ndjson {
+        println!("{}", stats_json(engine.stats()));
+    }
 
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Records handled paths; scans answer from a fixed table
+    #[derive(Default)]
+    struct Fake {
+        handled: Vec<PathBuf>,
+        trees: HashMap<PathBuf, Vec<PathBuf>>,
+        outcome: Option<Outcome>,
+        missing: Vec<PathBuf>,
+        watched: Vec<PathBuf>,
+    }
+
+    impl Executor for Fake {
+        fn handle(&mut self, path: &Path) -> Outcome {
+            self.handled.push(path.to_path_buf());
+            self.outcome.unwrap_or(Outcome::Deleted)
+        }
+
+        fn scan(&mut self, dir: &Path, _: Traversal) -> Vec<PathBuf> {
+            self.trees.get(dir).cloned().unwrap_or_default()
+        }
+
+        fn root_exists(&mut self, root: &Path) -> bool {
+            !self.missing.contains(&root.to_path_buf())
+        }
+
+        fn watch(&mut self, root: &Path) -> Result<(), String> {
+            self.watched.push(root.to_path_buf());
+            Ok(())
+        }
+
+        fn unwatch(&mut self, root: &Path) {
+            self.watched.retain(|r| r != root);
+        }
+    }
+
+    #[test]
+    fn test_event_json() {
+        let line = event_json(WatchEvent::SkippedGit, Some(Path::new("/r/.DS_Store")), None);
+        assert!(line.starts_with(r#"{"event":"skipped-git","ts":"#));
+        assert!(line.ends_with(r#","path":"/r/.DS_Store"}"#));
+
+        let line = event_json(WatchEvent::Error, None, Some("queue overflow"));
+        assert!(line.ends_with(r#","message":"queue overflow"}"#));
+        assert!(!line.contains("\n"));
+    }
+
+    #[test]
+    fn test_translate() {
+        use notify::event::{DataChange, RemoveKind};
+
+        let ev = |kind, paths: &[&str]| {
+            paths.iter().fold(Event::new(kind), |e, p| e.add_path(PathBuf::from(p)))
+        };
+
+        assert_eq!(
+            translate(ev(EventKind::Create(CreateKind::File), &["/a"])),
+            vec![FsEvent::Created("/a".into())]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Remove(RemoveKind::File), &["/a"])),
+            vec![FsEvent::Removed("/a".into())]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/a", "/b"])),
+            vec![FsEvent::Renamed { from: "/a".into(), to: "/b".into() }]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Modify(ModifyKind::Name(RenameMode::To)), &["/b"])),
+            vec![FsEvent::Created("/b".into())]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &["/a"])),
+            vec![FsEvent::Modified("/a".into())]
+        );
+        assert!(translate(ev(EventKind::Other, &["/a"])).is_empty());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let d = dir.path().to_str().unwrap();
+        assert_eq!(
+            translate(ev(EventKind::Create(CreateKind::Folder), &["/new"])),
+            vec![FsEvent::DirCreated("/new".into())]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/old", d])),
+            vec![FsEvent::Removed("/old".into()), FsEvent::DirCreated(d.into())]
+        );
+        assert_eq!(
+            translate(ev(EventKind::Modify(ModifyKind::Name(RenameMode::Any)), &[d])),
+            vec![FsEvent::DirCreated(d.into())]
+        );
+    }
+
+    fn modified(path: &str) -> Msg {
+        Msg::Fs(FsEvent::Modified(path.into()))
+    }
+
+    #[test]
+    fn test_drive_coalesces_burst() {
+        let (tx, rx) = channel();
+        tx.send(Msg::Fs(FsEvent::Created("/a/.DS_Store".into()))).unwrap();
+        for _ in 0..5 {
+            tx.send(modified("/a/.DS_Store")).unwrap();
+        }
+        tx.send(modified("/b/.DS_Store")).unwrap();
+        tx.send(modified("/b/notes.txt")).unwrap();
+        tx.send(Msg::Fs(FsEvent::Created("/c/.DS_Store".into()))).unwrap();
+        tx.send(Msg::Fs(FsEvent::Removed("/c/.DS_Store".into()))).unwrap();
+        drop(tx);
+
+        let mut engine = Engine::new(Duration::from_millis(50));
+        let mut fake = Fake::default();
+        drive(&rx, &mut engine, false, &mut fake);
+
+        let handled = fake.handled;
+        assert_eq!(handled.len(), 2, "one action per path: {:?}", handled);
+        assert!(handled.contains(&"/a/.DS_Store".into()));
+        assert!(handled.contains(&"/b/.DS_Store".into()));
+        assert_eq!(engine.stats().deleted, 2);
+    }
+
+    #[test]
+    fn test_drive_acts_after_quiet_period() {
+        let (tx, rx) = channel();
+        let sender = std::thread::spawn(move || {
+            tx.send(modified("/a/.DS_Store")).unwrap();
+            std::thread::sleep(Duration::from_millis(150));
+            tx.send(modified("/a/.DS_Store")).unwrap();
+            std::thread::sleep(Duration::from_millis(150));
+        });
+
+        /// Records when each path was handled
+        struct Timed(Instant, Vec<Duration>);
+        impl Executor for Timed {
+            fn handle(&mut self, _: &Path) -> Outcome {
+                self.1.push(self.0.elapsed());
+                Outcome::Deleted
+            }
+            fn scan(&mut self, _: &Path, _: Traversal) -> Vec<PathBuf> {
+                Vec::new()
+            }
+            fn root_exists(&mut self, _: &Path) -> bool {
+                true
+            }
+            fn watch(&mut self, _: &Path) -> Result<(), String> {
+                Ok(())
+            }
+            fn unwatch(&mut self, _: &Path) {}
+        }
+
+        let mut engine = Engine::new(Duration::from_millis(30));
+        let mut timed = Timed(Instant::now(), Vec::new());
+        drive(&rx, &mut engine, false, &mut timed);
+        sender.join().unwrap();
+
+        // Two separate bursts, each handled once after its quiet period
+        let handled = timed.1;
+        assert_eq!(handled.len(), 2);
+        assert!(handled[0] >= Duration::from_millis(30));
+        assert!(handled[0] < Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_drive_shutdown_flushes_pending() {
+        let (tx, rx) = channel();
+        tx.send(modified("/a/.DS_Store")).unwrap();
+        tx.send(Msg::Shutdown).unwrap();
+        tx.send(modified("/late/.DS_Store")).unwrap();
+
+        // Long debounce: only the shutdown flush can release the target,
+        // and the sender is still alive so only Shutdown ends the loop.
+        let mut engine = Engine::new(Duration::from_secs(60));
+        let mut fake = Fake { outcome: Some(Outcome::SkippedGit), ..Default::default() };
+        drive(&rx, &mut engine, false, &mut fake);
+
+        assert_eq!(fake.handled, vec![PathBuf::from("/a/.DS_Store")]);
+        assert_eq!(engine.stats().skipped_git, 1);
+        drop(tx);
+    }
+
+    #[test]
+    fn test_drive_scans_moved_in_directory() {
+        let (tx, rx) = channel();
+        tx.send(Msg::Fs(FsEvent::DirCreated("/w/album".into()))).unwrap();
+        tx.send(Msg::Fs(FsEvent::DirCreated("/w/album/disc1".into()))).unwrap();
+        drop(tx);
+
+        let mut fake = Fake::default();
+        fake.trees.insert(
+            "/w/album".into(),
+            vec!["/w/album/.DS_Store".into(), "/w/album/disc1/.DS_Store".into()],
+        );
+
+        let mut engine = Engine::new(Duration::from_millis(10));
+        drive(&rx, &mut engine, false, &mut fake);
+
+        assert_eq!(
+            fake.handled,
+            vec![PathBuf::from("/w/album/.DS_Store"), PathBuf::from("/w/album/disc1/.DS_Store")]
+        );
+        assert_eq!(engine.stats().deleted, 2);
+    }
+
+    #[test]
+    fn test_drive_rescans_on_overflow() {
+        use notify::event::Flag;
+
+        let (tx, rx) = channel();
+        let sender = std::thread::spawn(move || {
+            for event in translate(Event::new(EventKind::Other).set_flag(Flag::Rescan)) {
+                tx.send(Msg::Fs(event)).unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        });
+
+        let mut fake = Fake::default();
+        fake.trees.insert("/r".into(), vec!["/r/deep/.DS_Store".into()]);
+
+        let now = Instant::now();
+        let mut engine = Engine::new(Duration::from_millis(10)).with_roots(
+            &["/r".into()],
+            Duration::from_secs(3600),
+            now,
+        );
+        drive(&rx, &mut engine, false, &mut fake);
+        sender.join().unwrap();
+
+        assert_eq!(fake.handled, vec![PathBuf::from("/r/deep/.DS_Store")]);
+        assert_eq!(engine.stats().rescans, 1);
+    }
+
+    #[test]
+    fn test_perform_rewatches_returning_root() {
+        let now = Instant::now();
+        let mut engine = Engine::new(Duration::from_millis(10)).with_roots(
+            &["/vol".into()],
+            Duration::from_secs(1),
+            now,
+        );
+        let mut fake = Fake { missing: vec!["/vol".into()], ..Default::default() };
+        fake.trees.insert("/vol".into(), vec!["/vol/.DS_Store".into()]);
+
+        perform(&mut engine, &mut fake, vec![Action::CheckRoots], false);
+        assert!(fake.watched.is_empty());
+
+        fake.missing.clear();
+        perform(&mut engine, &mut fake, vec![Action::CheckRoots], false);
+        assert_eq!(fake.watched, vec![PathBuf::from("/vol")]);
+        assert_eq!(fake.handled, vec![PathBuf::from("/vol/.DS_Store")]);
+    }
+
+    #[test]
+    fn test_control_requests() {
+        let t0 = Instant::now();
+        let mut engine = Engine::new(Duration::ZERO).with_roots(
+            &["/r".into()],
+            Duration::from_secs(3600),
+            t0,
+        );
+        let mut fake = Fake::default();
+        fake.trees.insert("/s".into(), vec!["/s/.DS_Store".into()]);
+        fake.missing.push("/gone".into());
+        let mut ask = |engine: &mut Engine, req| control(engine, &mut fake, req, Duration::ZERO, false);
+
+        assert!(ask(&mut engine, Request::AddRoot("/gone".into())).is_err());
+        assert!(ask(&mut engine, Request::AddRoot("/r/sub".into())).is_err());
+        assert!(ask(&mut engine, Request::AddRoot("/s".into())).is_ok());
+        assert_eq!(engine.stats().deleted, 1);
+
+        assert_eq!(ask(&mut engine, Request::Pause), Ok("paused".into()));
+        let stats = ask(&mut engine, Request::Stats).unwrap();
+        assert!(stats.starts_with(r#"{"paused":true,"paused_paths":[],"uptime_secs":0,"queue":0,"roots":[{"path":"/r","up":true},{"path":"/s","up":true}],"#));
+        assert!(stats.contains(r#""deleted":1,"#));
+        assert!(ask(&mut engine, Request::Rescan("/s".into())).is_err());
+
+        ask(&mut engine, Request::Resume).unwrap();
+        assert!(ask(&mut engine, Request::RemoveRoot("/s".into())).is_ok());
+        assert!(ask(&mut engine, Request::RemoveRoot("/s".into())).is_err());
+        assert_eq!(fake.watched, Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn test_distinct_roots() {
+        let roots = distinct_roots(&[
+            PathBuf::from("/b/inner"),
+            PathBuf::from("/a"),
+            PathBuf::from("/b"),
+            PathBuf::from("/a"),
+            PathBuf::from("/ab"),
+        ]);
+        assert_eq!(roots, vec![PathBuf::from("/a"), PathBuf::from("/b"), PathBuf::from("/ab")]);
+    }
+
+    #[test]
+    fn test_watch_mode() {
+        let mut opts = WatchOptions {
+            excludes: vec![],
+            notify: false,
+            force: false,
+            ndjson: false,
+            debounce: Duration::ZERO,
+            sweep: None,
+            poll: None,
+            poll_paths: vec![],
+            auto_poll: true,
+            full_scan: false,
+            socket: None,
+            pauses: vec![],
+            protect: Protector::default(),
+            filter: Filter::default(),
+            traversal: Traversal::default(),
+        };
+        let root = Path::new("/mnt/nas");
+        let default = Duration::from_secs(POLL_SECS);
+
+        assert_eq!(watch_mode(root, Some("ext4"), &opts), Mode::Events);
+        assert_eq!(watch_mode(root, None, &opts), Mode::Events);
+        assert_eq!(
+            watch_mode(root, Some("nfs4"), &opts),
+            Mode::Poll { every: default, reason: "nfs4".into() }
+        );
+
+        opts.auto_poll = false;
+        assert_eq!(watch_mode(root, Some("nfs4"), &opts), Mode::Events);
+
+        // --poll alone applies to every root
+        opts.poll = Some(Duration::from_secs(30));
+        assert!(matches!(watch_mode(root, Some("ext4"), &opts), Mode::Poll { .. }));
+
+        // --poll-path narrows it to the listed roots
+        opts.poll_paths = vec!["/mnt/smb".into()];
+        assert_eq!(watch_mode(root, Some("ext4"), &opts), Mode::Events);
+        assert_eq!(
+            watch_mode(Path::new("/mnt/smb"), Some("ext4"), &opts),
+            Mode::Poll { every: Duration::from_secs(30), reason: "--poll-path".into() }
+        );
+    }
+
+    #[test]
+    fn test_stats_json() {
+        let stats = WatchStats { events: 4, deleted: 2, ..Default::default() };
+        let line = stats_json(&stats);
+        assert!(line.starts_with(r#"{"event":"stopped","ts":"#));
+        assert!(line.ends_with(
+            r#","events":4,"deleted":2,"skipped_git":0,"excluded":0,"protected":0,"filtered":0,"errors":0,"rescans":0}"#
+        ));
+    }
+}
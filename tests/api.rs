@@ -0,0 +1,119 @@
+//! Integration tests for the public library API
+
+use ds_store_killer::{
+    Filter, KillObserver, KillOptions, Protector, Reason, Scanner, Skipped, git, is_excluded,
+    is_target, kill_files, kill_files_with,
+};
+use std::{
+    fs::{self, File},
+    path::Path,
+    process::Command,
+};
+use tempfile::TempDir;
+
+const TARGET: &str = ".DS_Store";
+
+/// Root and two subdirectories, each holding a target
+fn tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for sub in ["", "src", "node_modules/pkg"] {
+        let d = dir.path().join(sub);
+        fs::create_dir_all(&d).unwrap();
+        File::create(d.join(TARGET)).unwrap();
+    }
+    fs::write(dir.path().join("src/main.rs"), "").unwrap();
+    dir
+}
+
+#[test]
+fn test_matchers() {
+    assert!(is_target(Path::new("/a/.DS_Store")));
+    assert!(!is_target(Path::new("/a/.DS_Store.bak")));
+    assert!(is_excluded(Path::new("/a/build/.DS_Store"), &["build".into()]));
+    assert!(!is_excluded(Path::new("/a/src/.DS_Store"), &["build".into()]));
+}
+
+#[test]
+fn test_scanner() {
+    let dir = tree();
+
+    assert_eq!(Scanner::new(dir.path()).scan(), vec![dir.path().join(TARGET)]);
+    assert_eq!(Scanner::new(dir.path()).recursive(true).scan().len(), 3);
+
+    let mut seen = Vec::new();
+    let count = Scanner::new(dir.path())
+        .recursive(true)
+        .exclude("node_modules")
+        .for_each(|p| seen.push(p.to_path_buf()));
+    assert_eq!(count, 2);
+    assert!(seen.iter().all(|p| is_target(p) && !is_excluded(p, &["node_modules".into()])));
+
+    let mut big = Filter::default();
+    big.min_size = Some(1);
+    assert!(Scanner::new(dir.path()).recursive(true).filter(big).scan().is_empty());
+}
+
+#[test]
+fn test_kill() {
+    let dir = tree();
+    let scanner = Scanner::new(dir.path()).recursive(true).excludes(["node_modules"]);
+
+    let dry = scanner.kill(&KillOptions::new().dry_run(true));
+    assert_eq!((dry.found, dry.deleted, dry.files.len()), (2, 0, 2));
+    assert!(dir.path().join(TARGET).exists());
+
+    let result = scanner.kill(&KillOptions::new());
+    assert_eq!((result.found, result.deleted), (2, 2));
+    assert!(result.failures.is_empty());
+    assert!(!dir.path().join(TARGET).exists());
+    assert!(dir.path().join("node_modules/pkg").join(TARGET).exists());
+    assert_eq!(result.to_string(), "Deleted 2 .DS_Store file(s)");
+}
+
+#[test]
+fn test_kill_files_with_protection() {
+    let dir = tree();
+    let files = Scanner::new(dir.path()).recursive(true).scan();
+
+    let opts = KillOptions::new().protect(Protector::new(&["node_modules".into()]));
+    let result = kill_files(&files, &opts);
+
+    assert_eq!(result.deleted, 2);
+    let kept = dir.path().join("node_modules/pkg").join(TARGET);
+    assert_eq!(result.protected, vec![(kept.clone(), Reason::Keep("node_modules".into()))]);
+    assert!(kept.exists());
+}
+
+#[test]
+fn test_git_safety() {
+    if !git::is_available() {
+        return;
+    }
+    let dir = tree();
+    let git = |args: &[&str]| {
+        Command::new("git").args(args).current_dir(dir.path()).output().unwrap();
+    };
+    git(&["init", "-q"]);
+    git(&["add", "-f", "src/.DS_Store"]);
+
+    let files = Scanner::new(dir.path()).recursive(true).exclude("node_modules").scan();
+    let safety = git::check_files(&files);
+    assert_eq!(safety.tracked, vec![dir.path().join("src").join(TARGET)]);
+    assert_eq!(safety.safe, vec![dir.path().join(TARGET)]);
+
+    // The same check inside the kill pipeline, reported to an observer
+    struct Tracked(Vec<std::path::PathBuf>);
+    impl KillObserver for Tracked {
+        fn on_skipped(&mut self, path: &Path, why: &Skipped) {
+            if *why == Skipped::GitTracked {
+                self.0.push(path.to_path_buf());
+            }
+        }
+    }
+    let mut tracked = Tracked(Vec::new());
+    let opts = KillOptions::new().skip_tracked(true);
+    let result = kill_files_with(&files, &opts, &mut tracked);
+    assert_eq!(result.deleted, 1);
+    assert_eq!(result.skipped, tracked.0);
+    assert!(dir.path().join("src").join(TARGET).exists());
+}
@@ -0,0 +1,30 @@
+//! Git safety without a git binary; in its own test binary because it
+//! clears `PATH` for the whole process
+
+use ds_store_killer::{KillOptions, Scanner, git, kill_files};
+use std::{env, fs::File};
+use tempfile::TempDir;
+
+#[test]
+fn test_without_git_everything_is_tracked() {
+    // SAFETY: the only test in this binary, so no other thread reads the
+    // environment
+    unsafe { env::set_var("PATH", "") };
+    assert!(!git::is_available());
+
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join(".DS_Store");
+    File::create(&target).unwrap();
+    let files = vec![target.clone()];
+
+    let safety = git::check_files(&files);
+    assert!(safety.safe.is_empty());
+    assert_eq!(safety.tracked, files);
+
+    let opts = KillOptions::new().skip_tracked(true);
+    let result = kill_files(&files, &opts);
+    assert_eq!((result.deleted, result.skipped.clone()), (0, files.clone()));
+    let result = Scanner::new(dir.path()).kill(&opts);
+    assert_eq!(result.skipped, files);
+    assert!(target.exists());
+}